@@ -0,0 +1,47 @@
+#import bevy_sprite::mesh2d_functions::{get_model_matrix, mesh2d_position_local_to_clip}
+
+@group(2) @binding(0)
+var atlas: texture_2d<f32>;
+@group(2) @binding(1)
+var atlas_sampler: sampler;
+@group(2) @binding(2)
+var<uniform> remembered_color: vec4<f32>;
+
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) position: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    // x: how much the corner is in view, y: whether the tile has been seen
+    @location(2) fog: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) fog: vec2<f32>,
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = mesh2d_position_local_to_clip(
+        get_model_matrix(vertex.instance_index),
+        vec4<f32>(vertex.position, 1.0),
+    );
+    out.uv = vertex.uv;
+    out.fog = vertex.fog;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Tiles never seen are left out
+    if in.fog.y <= 0.0 {
+        discard;
+    }
+
+    let color = textureSample(atlas, atlas_sampler, in.uv);
+    // Remembered tiles are tinted, the tint fades out towards the tiles in view
+    let tint = mix(remembered_color.rgb, vec3<f32>(1.0), smoothstep(0.0, 1.0, in.fog.x));
+    return vec4<f32>(color.rgb * tint, color.a);
+}
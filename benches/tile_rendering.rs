@@ -0,0 +1,100 @@
+//! Compares redrawing every tile with redrawing only the dirty ones on a 256x256 map
+//! Run with `cargo bench --bench tile_rendering`
+
+use bevy::ecs::system::RunSystemOnce;
+use bevy::prelude::*;
+use bracket_pathfinding::prelude::*;
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use roguelike::loading::TextureAssets;
+use roguelike::map::{update_map, Map, Rect, Tile};
+use roguelike::tilemap::{spawn_tile_chunks, FogOfWar, TilemapMaterial};
+
+const MAP_SIZE: usize = 256;
+const VIEW_RANGE: i32 = 8;
+
+fn setup_world() -> World {
+    let mut map = Map::new(MAP_SIZE, MAP_SIZE, 16);
+    // A grid of rooms joined by corridors
+    for y in (1..MAP_SIZE - 12).step_by(12) {
+        for x in (1..MAP_SIZE - 12).step_by(12) {
+            map.set_rect(&Rect::new(x, y, 9, 9), Tile::Floor);
+            map.set_horizontal_line(x + 9, x + 12, y + 4, Tile::Floor);
+            map.set_vertical_line(x + 4, y + 9, y + 12, Tile::Floor);
+        }
+    }
+    map.compute_wall_masks();
+
+    let mut world = World::new();
+    world.insert_resource(map);
+    world.insert_resource(TextureAssets {
+        icon_bevy: Handle::default(),
+        icon_github: Handle::default(),
+        map_atlas_layout: Handle::default(),
+        map_atlas: Handle::default(),
+        heart: Handle::default(),
+    });
+    world.init_resource::<FogOfWar>();
+    world.init_resource::<Assets<Mesh>>();
+    world.init_resource::<Assets<TilemapMaterial>>();
+    world.run_system_once(spawn_tiles);
+    world
+}
+
+fn spawn_tiles(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<TilemapMaterial>>,
+    map: Res<Map>,
+    texture_assets: Res<TextureAssets>,
+) {
+    let map_chunks = spawn_tile_chunks(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        &map,
+        &texture_assets,
+        &FogOfWar::default(),
+    );
+    commands.insert_resource(map_chunks);
+}
+
+/// The player goes back and forth between two tiles, moving the field of view each frame
+fn step_player(mut map: ResMut<Map>, mut step: Local<i32>) {
+    *step = 1 - *step;
+    let center = Point::new(MAP_SIZE as i32 / 2 + *step, MAP_SIZE as i32 / 2 - 6);
+    let visible: Vec<Point> = field_of_view(center, VIEW_RANGE, &*map)
+        .into_iter()
+        .filter(|p| map.in_bounds(*p))
+        .collect();
+    map.update_visible_tiles(&visible);
+}
+
+/// What refreshing every tile on every frame costs
+fn redraw_everything(mut map: ResMut<Map>) {
+    map.mark_all_dirty();
+}
+
+fn tile_rendering(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tile_rendering_256x256");
+
+    let mut world = setup_world();
+    let mut schedule = Schedule::default();
+    schedule.add_systems((step_player, redraw_everything, update_map).chain());
+    group.bench_function("full_refresh", |b| b.iter(|| schedule.run(&mut world)));
+
+    let mut world = setup_world();
+    let mut schedule = Schedule::default();
+    schedule.add_systems((step_player, update_map).chain());
+    group.bench_function("dirty_tiles", |b| b.iter(|| schedule.run(&mut world)));
+
+    let mut world = setup_world();
+    let mut schedule = Schedule::default();
+    schedule.add_systems(update_map);
+    group.bench_function("idle", |b| b.iter(|| schedule.run(&mut world)));
+
+    group.finish();
+}
+
+criterion_group!(benches, tile_rendering);
+criterion_main!(benches);
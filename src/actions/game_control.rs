@@ -5,6 +5,20 @@ pub enum GameControl {
     Down,
     Left,
     Right,
+    Descend,
+    Ascend,
+    CloseDoor,
+    ZoomIn,
+    ZoomOut,
+    Minimap,
+    Pickup,
+    Drink,
+    Read,
+    Equip,
+    Equipment,
+    Inventory,
+    Confirm,
+    Cancel,
 }
 
 impl GameControl {
@@ -26,10 +40,73 @@ impl GameControl {
                 keyboard_input.just_pressed(KeyCode::KeyD)
                     || keyboard_input.just_pressed(KeyCode::ArrowRight)
             }
+            GameControl::Descend => {
+                keyboard_input.just_pressed(KeyCode::Period)
+                    || keyboard_input.just_pressed(KeyCode::NumpadEnter)
+            }
+            GameControl::Ascend => keyboard_input.just_pressed(KeyCode::Comma),
+            GameControl::CloseDoor => keyboard_input.just_pressed(KeyCode::KeyC),
+            GameControl::ZoomIn => {
+                keyboard_input.just_pressed(KeyCode::Equal)
+                    || keyboard_input.just_pressed(KeyCode::NumpadAdd)
+            }
+            GameControl::ZoomOut => {
+                keyboard_input.just_pressed(KeyCode::Minus)
+                    || keyboard_input.just_pressed(KeyCode::NumpadSubtract)
+            }
+            GameControl::Minimap => keyboard_input.just_pressed(KeyCode::KeyM),
+            GameControl::Pickup => keyboard_input.just_pressed(KeyCode::KeyG),
+            GameControl::Drink => keyboard_input.just_pressed(KeyCode::KeyQ),
+            GameControl::Read => keyboard_input.just_pressed(KeyCode::KeyR),
+            GameControl::Equip => keyboard_input.just_pressed(KeyCode::KeyF),
+            GameControl::Equipment => keyboard_input.just_pressed(KeyCode::KeyE),
+            GameControl::Inventory => keyboard_input.just_pressed(KeyCode::KeyI),
+            GameControl::Confirm => {
+                keyboard_input.just_pressed(KeyCode::Enter)
+                    || keyboard_input.just_pressed(KeyCode::Space)
+            }
+            GameControl::Cancel => keyboard_input.just_pressed(KeyCode::Escape),
         }
     }
 }
 
+const LETTERS: [(KeyCode, char); 26] = [
+    (KeyCode::KeyA, 'a'),
+    (KeyCode::KeyB, 'b'),
+    (KeyCode::KeyC, 'c'),
+    (KeyCode::KeyD, 'd'),
+    (KeyCode::KeyE, 'e'),
+    (KeyCode::KeyF, 'f'),
+    (KeyCode::KeyG, 'g'),
+    (KeyCode::KeyH, 'h'),
+    (KeyCode::KeyI, 'i'),
+    (KeyCode::KeyJ, 'j'),
+    (KeyCode::KeyK, 'k'),
+    (KeyCode::KeyL, 'l'),
+    (KeyCode::KeyM, 'm'),
+    (KeyCode::KeyN, 'n'),
+    (KeyCode::KeyO, 'o'),
+    (KeyCode::KeyP, 'p'),
+    (KeyCode::KeyQ, 'q'),
+    (KeyCode::KeyR, 'r'),
+    (KeyCode::KeyS, 's'),
+    (KeyCode::KeyT, 't'),
+    (KeyCode::KeyU, 'u'),
+    (KeyCode::KeyV, 'v'),
+    (KeyCode::KeyW, 'w'),
+    (KeyCode::KeyX, 'x'),
+    (KeyCode::KeyY, 'y'),
+    (KeyCode::KeyZ, 'z'),
+];
+
+/// The letter key pressed this frame, for menu hotkeys
+pub fn get_letter(input: &Res<ButtonInput<KeyCode>>) -> Option<char> {
+    LETTERS
+        .iter()
+        .find(|(key, _)| input.just_pressed(*key))
+        .map(|(_, letter)| *letter)
+}
+
 pub fn get_movement(control: GameControl, input: &Res<ButtonInput<KeyCode>>) -> i32 {
     if control.just_pressed(input) {
         1
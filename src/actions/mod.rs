@@ -1,9 +1,11 @@
+use bevy::input::mouse::MouseWheel;
 use bevy::math::Vec3Swizzles;
 use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
 
-use crate::actions::game_control::{get_movement, GameControl};
+use crate::actions::game_control::{get_letter, get_movement, GameControl};
 use crate::player::Player;
-use crate::GameState;
+use crate::{GameState, PlayState};
 
 mod game_control;
 
@@ -15,10 +17,19 @@ pub struct ActionsPlugin;
 // Actions can then be used as a resource in other systems to act on the player input.
 impl Plugin for ActionsPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<Actions>().add_systems(
-            Update,
-            set_movement_actions.run_if(in_state(GameState::Playing)),
-        );
+        app.init_resource::<Actions>()
+            .add_systems(OnExit(PlayState::Running), clear_actions)
+            .add_systems(
+                Update,
+                (
+                    set_movement_actions.run_if(in_state(PlayState::Running)),
+                    set_targeting_actions.run_if(in_state(PlayState::Targeting)),
+                    set_menu_actions.run_if(
+                        in_state(PlayState::Inventory).or_else(in_state(PlayState::Equipment)),
+                    ),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            );
     }
 }
 
@@ -26,12 +37,37 @@ impl Plugin for ActionsPlugin {
 pub struct Actions {
     pub player_movement: Option<(i32, i32)>,
     pub attack: bool,
+    pub descend: bool,
+    pub ascend: bool,
+    pub close_door: bool,
+    /// Zoom steps asked for this frame, positive to zoom in
+    pub zoom: i32,
+    pub toggle_minimap: bool,
+    pub pickup: bool,
+    /// Drink the first healing potion in the backpack
+    pub drink: bool,
+    /// Read the first scroll in the backpack
+    pub read: bool,
+    /// Put on the first piece of equipment in the backpack
+    pub equip: bool,
+    pub toggle_inventory: bool,
+    pub toggle_equipment: bool,
+    /// Letter pressed in a menu
+    pub menu_key: Option<char>,
+    pub cursor_movement: Option<(i32, i32)>,
+    /// Where the mouse has moved to, in world coordinates
+    pub cursor_world: Option<Vec2>,
+    pub confirm: bool,
+    /// The confirm is a mouse click on the tile under `cursor_world`
+    pub click: bool,
+    pub cancel: bool,
 }
 
 pub fn set_movement_actions(
     mut actions: ResMut<Actions>,
     keyboard_input: Res<ButtonInput<KeyCode>>,
     touch_input: Res<Touches>,
+    mut ev_mouse_wheel: EventReader<MouseWheel>,
     player: Query<&Transform, With<Player>>,
     camera: Query<(&Camera, &GlobalTransform), With<Camera2d>>,
 ) {
@@ -47,4 +83,80 @@ pub fn set_movement_actions(
     } else {
         actions.player_movement = None;
     }
+
+    actions.descend = GameControl::Descend.just_pressed(&keyboard_input);
+    actions.ascend = GameControl::Ascend.just_pressed(&keyboard_input);
+    actions.close_door = GameControl::CloseDoor.just_pressed(&keyboard_input);
+    actions.toggle_minimap = GameControl::Minimap.just_pressed(&keyboard_input);
+    actions.pickup = GameControl::Pickup.just_pressed(&keyboard_input);
+    actions.drink = GameControl::Drink.just_pressed(&keyboard_input);
+    actions.read = GameControl::Read.just_pressed(&keyboard_input);
+    actions.equip = GameControl::Equip.just_pressed(&keyboard_input);
+    actions.toggle_inventory = GameControl::Inventory.just_pressed(&keyboard_input);
+    actions.toggle_equipment = GameControl::Equipment.just_pressed(&keyboard_input);
+
+    let scrolled: f32 = ev_mouse_wheel.read().map(|ev| ev.y).sum();
+    actions.zoom = get_movement(GameControl::ZoomIn, &keyboard_input)
+        - get_movement(GameControl::ZoomOut, &keyboard_input)
+        + if scrolled == 0.0 {
+            0
+        } else {
+            scrolled.signum() as i32
+        };
+}
+
+/// Moves the targeting cursor instead of the player
+pub fn set_targeting_actions(
+    mut actions: ResMut<Actions>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    mut ev_cursor_moved: EventReader<CursorMoved>,
+    camera: Query<(&Camera, &GlobalTransform), With<Camera2d>>,
+    q_window: Query<&Window, With<PrimaryWindow>>,
+) {
+    let cursor_movement = (
+        get_movement(GameControl::Right, &keyboard_input)
+            - get_movement(GameControl::Left, &keyboard_input),
+        get_movement(GameControl::Up, &keyboard_input)
+            - get_movement(GameControl::Down, &keyboard_input),
+    );
+
+    if cursor_movement != (0, 0) {
+        actions.cursor_movement = Some(cursor_movement);
+    } else {
+        actions.cursor_movement = None;
+    }
+
+    actions.click = mouse_input.just_pressed(MouseButton::Left);
+    // A click is where the mouse is, even when it didn't move this frame
+    let cursor_position = ev_cursor_moved
+        .read()
+        .last()
+        .map(|ev| ev.position)
+        .or_else(|| {
+            actions
+                .click
+                .then(|| q_window.get_single().ok()?.cursor_position())
+                .flatten()
+        });
+    actions.cursor_world = cursor_position.and_then(|position| {
+        let (camera, camera_transform) = camera.get_single().ok()?;
+        camera.viewport_to_world_2d(camera_transform, position)
+    });
+    actions.confirm = GameControl::Confirm.just_pressed(&keyboard_input) || actions.click;
+    actions.cancel = GameControl::Cancel.just_pressed(&keyboard_input)
+        || mouse_input.just_pressed(MouseButton::Right);
+}
+
+/// Picks from the inventory and equipment screens
+pub fn set_menu_actions(mut actions: ResMut<Actions>, keyboard_input: Res<ButtonInput<KeyCode>>) {
+    actions.toggle_inventory = GameControl::Inventory.just_pressed(&keyboard_input);
+    actions.toggle_equipment = GameControl::Equipment.just_pressed(&keyboard_input);
+    actions.cancel = GameControl::Cancel.just_pressed(&keyboard_input);
+    actions.menu_key = get_letter(&keyboard_input);
+}
+
+/// Nothing asked for before a pause should happen once it is over
+fn clear_actions(mut actions: ResMut<Actions>) {
+    *actions = Actions::default();
 }
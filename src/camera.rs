@@ -0,0 +1,96 @@
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use bevy::window::PrimaryWindow;
+
+use crate::actions::{set_movement_actions, Actions};
+use crate::map::Map;
+use crate::player::Player;
+use crate::{GameState, HUD_ROWS};
+
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 4.0;
+/// Factor applied to the projection scale for every zoom step
+const ZOOM_STEP: f32 = 1.25;
+
+pub struct CameraPlugin;
+
+/// This plugin keeps the camera on the player without showing anything outside the map
+/// The view can be zoomed in and out during the State `GameState::Playing`
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            zoom_camera
+                .after(set_movement_actions)
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(
+            PostUpdate,
+            follow_player
+                .before(TransformSystem::TransformPropagate)
+                .run_if(in_state(GameState::Playing)),
+        );
+    }
+}
+
+fn zoom_camera(
+    actions: Res<Actions>,
+    mut q_projection: Query<&mut OrthographicProjection, With<Camera2d>>,
+) {
+    if actions.zoom == 0 {
+        return;
+    }
+    let Ok(mut projection) = q_projection.get_single_mut() else {
+        return;
+    };
+
+    // Zooming in makes the world bigger, so the projection scale goes down
+    let scale = projection.scale * ZOOM_STEP.powi(-actions.zoom);
+    projection.scale = scale.clamp(MIN_ZOOM, MAX_ZOOM);
+}
+
+fn follow_player(
+    map: Res<Map>,
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    q_player: Query<&Transform, (With<Player>, Without<Camera2d>)>,
+    mut q_camera: Query<(&mut Transform, &OrthographicProjection), With<Camera2d>>,
+) {
+    let Ok(player_transform) = q_player.get_single() else {
+        return;
+    };
+    let Ok((mut camera_transform, projection)) = q_camera.get_single_mut() else {
+        return;
+    };
+    let Ok(window) = q_window.get_single() else {
+        return;
+    };
+
+    let tile_size = map.tile_size as f32;
+    let view = Vec2::new(window.width(), window.height()) * projection.scale;
+    // The HUD covers the bottom of the window
+    let hud = HUD_ROWS * tile_size * projection.scale;
+
+    // Tiles are centered on their position
+    let map_min = Vec2::splat(-tile_size / 2.0);
+    let map_max = Vec2::new(map.cols as f32, map.rows as f32) * tile_size - tile_size / 2.0;
+
+    let target = player_transform.translation.truncate();
+    let x = clamp_axis(target.x, map_min.x + view.x / 2.0, map_max.x - view.x / 2.0);
+    let y = clamp_axis(
+        target.y,
+        map_min.y + view.y / 2.0 - hud,
+        map_max.y - view.y / 2.0,
+    );
+
+    camera_transform.translation.x = x;
+    camera_transform.translation.y = y;
+}
+
+/// Keeps the camera between the bounds, or halfway when the map is smaller than the view
+fn clamp_axis(value: f32, min: f32, max: f32) -> f32 {
+    if min > max {
+        (min + max) / 2.0
+    } else {
+        value.clamp(min, max)
+    }
+}
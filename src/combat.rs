@@ -1,5 +1,7 @@
-use bevy::{prelude::*, utils::hashbrown::HashMap};
+use bevy::prelude::*;
 
+use crate::equipment::{equipment_bonus, DefenseBonus, Equipped, MeleePowerBonus};
+use crate::map::{Map, Position};
 use crate::{player::PlayerEntity, GameState};
 
 pub struct CombatPlugin;
@@ -11,11 +13,10 @@ impl Plugin for CombatPlugin {
             .register_type::<SufferDamage>()
             .add_systems(
                 Update,
-                (
-                    melee_combat.run_if(in_state(GameState::Playing)),
-                    apply_damage.run_if(in_state(GameState::Playing)),
-                    delete_the_dead.run_if(in_state(GameState::Playing)),
-                ),
+                // The dead are only removed once everyone had a go at them
+                (melee_combat, tile_damage, apply_damage, delete_the_dead)
+                    .chain()
+                    .run_if(in_state(GameState::Playing)),
             );
     }
 }
@@ -38,49 +39,71 @@ pub struct SufferDamage {
     pub amount: Vec<i32>,
 }
 
+impl SufferDamage {
+    /// Adds to what the victim takes at the end of the turn, on top of whatever hurt it already
+    pub fn new_damage(commands: &mut Commands, victim: Entity, amount: i32) {
+        commands.add(move |world: &mut World| {
+            let Some(mut victim) = world.get_entity_mut(victim) else {
+                return;
+            };
+            if let Some(mut suffer_damage) = victim.get_mut::<SufferDamage>() {
+                suffer_damage.amount.push(amount);
+            } else {
+                victim.insert(SufferDamage {
+                    amount: vec![amount],
+                });
+            }
+        });
+    }
+}
+
 pub fn melee_combat(
     mut commands: Commands,
     q_wants_to_melee: Query<(Entity, &Parent, &WantsToMelee)>,
-    mut q_combat_stats: Query<(&CombatStats, &Name, Option<&mut SufferDamage>)>,
+    q_combat_stats: Query<(&CombatStats, &Name)>,
+    q_equipment: Query<(&Equipped, Option<&MeleePowerBonus>, Option<&DefenseBonus>)>,
 ) {
-    let mut damage_map: HashMap<Entity, Vec<i32>> = HashMap::default();
-
     for (entity, parent, wants_to_melee) in q_wants_to_melee.iter() {
-        let (active, active_name, _) = q_combat_stats.get(parent.get()).unwrap();
+        let (active, active_name) = q_combat_stats.get(parent.get()).unwrap();
         if active.hp < 0 {
             continue;
         }
 
-        let (unactive, unactive_name, _) = q_combat_stats.get(wants_to_melee.target).unwrap();
+        let (unactive, unactive_name) = q_combat_stats.get(wants_to_melee.target).unwrap();
         if unactive.hp < 0 {
             continue;
         }
 
-        let damage = 0.max(active.power - unactive.defense);
+        // What is worn adds up to the raw stats
+        let (power_bonus, _) = equipment_bonus(parent.get(), &q_equipment);
+        let (_, defense_bonus) = equipment_bonus(wants_to_melee.target, &q_equipment);
+        let damage = 0.max(active.power + power_bonus - (unactive.defense + defense_bonus));
         if damage > 0 {
-            if let Some(suffer_damages) = damage_map.get_mut(&wants_to_melee.target) {
-                suffer_damages.push(damage);
-            } else {
-                damage_map.insert(wants_to_melee.target, vec![damage]);
-            }
+            info!("{} is damaged for {} hp", unactive_name, damage);
+            SufferDamage::new_damage(&mut commands, wants_to_melee.target, damage);
         } else {
             info!("{} is unable to hurt {}", active_name, unactive_name);
         }
 
         commands.entity(entity).despawn_recursive();
     }
+}
 
-    damage_map.into_iter().for_each(|(entity, damages)| {
-        let (_, name, suffer_damage) = q_combat_stats.get_mut(entity).unwrap();
-
-        if let Some(mut suffer_damage) = suffer_damage {
-            suffer_damage.amount.extend_from_slice(&damages);
-        } else {
-            info!("{} is damaged for {} hp", name, damages.iter().sum::<i32>());
-            commands
-                .entity(entity)
-                .insert(SufferDamage { amount: damages });
+/// Hurts whoever has just stepped onto a harmful tile like lava or a trap
+pub fn tile_damage(
+    mut commands: Commands,
+    map: Res<Map>,
+    q_moved: Query<(Entity, &Position, &Name), (With<CombatStats>, Changed<Position>)>,
+) {
+    q_moved.iter().for_each(|(entity, pos, name)| {
+        let tile = map.get_tile(pos.x, pos.y);
+        let damage = tile.damage_on_enter();
+        if damage <= 0 {
+            return;
         }
+
+        info!("{} is hurt by {:?} for {} hp", name, tile, damage);
+        SufferDamage::new_damage(&mut commands, entity, damage);
     });
 }
 
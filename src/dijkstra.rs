@@ -0,0 +1,153 @@
+use bevy::prelude::*;
+use bracket_pathfinding::prelude::*;
+
+use crate::door::DoorKey;
+use crate::item::Item;
+use crate::map::{map_index, Depth, Map, Position, Terrain, Tile};
+use crate::player::{player_input, Player};
+use crate::GameState;
+
+/// How far the distances are followed, anything further is unreachable
+const MAX_DEPTH: f32 = 1024.0;
+/// Scales the distances to the player before they are flattened into the flee map
+/// Going past 1.0 makes fleeing monsters prefer open areas over dead ends
+const FLEE_FACTOR: f32 = -1.2;
+
+pub struct DijkstraPlugin;
+
+/// This plugin keeps the shared Dijkstra maps up to date
+/// Each one only gets rebuilt when its starts move or the map changes
+impl Plugin for DijkstraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DijkstraMaps>()
+            .add_systems(OnExit(GameState::Playing), reset_dijkstra_maps)
+            .add_systems(
+                Update,
+                update_dijkstra_maps
+                    .after(player_input)
+                    .after(map_index)
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+/// Distances over the map terrain, monsters don't block them
+/// Unreachable tiles are `f32::MAX`
+#[derive(Resource, Default)]
+pub struct DijkstraMaps {
+    pub to_player: Vec<f32>,
+    pub to_stairs: Vec<f32>,
+    /// Distances to the closest item or key lying on the floor
+    pub to_items: Vec<f32>,
+    /// Lower is further away from the player, follow it downhill to run away
+    pub flee: Vec<f32>,
+
+    player: Option<usize>,
+    items: Vec<usize>,
+    map_revision: Option<(usize, u64)>,
+}
+
+impl DijkstraMaps {
+    /// The next tile on the way to the player
+    pub fn step_towards_player(&self, map: &Map, idx: usize) -> Option<usize> {
+        Self::downhill(&self.to_player, map, idx)
+    }
+
+    pub fn step_away_from_player(&self, map: &Map, idx: usize) -> Option<usize> {
+        Self::downhill(&self.flee, map, idx)
+    }
+
+    pub fn step_towards_stairs(&self, map: &Map, idx: usize) -> Option<usize> {
+        Self::downhill(&self.to_stairs, map, idx)
+    }
+
+    /// The next tile on the way to the closest item or key on the floor
+    pub fn step_towards_items(&self, map: &Map, idx: usize) -> Option<usize> {
+        Self::downhill(&self.to_items, map, idx)
+    }
+
+    /// Picks the free neighbour with the lowest value, if it is lower than the current tile
+    pub fn downhill(values: &[f32], map: &Map, idx: usize) -> Option<usize> {
+        if values.len() != map.cols * map.rows {
+            return None;
+        }
+
+        map.get_available_exits(idx)
+            .into_iter()
+            .map(|(exit, _)| exit)
+            .filter(|exit| values[*exit] < values[idx])
+            .min_by(|a, b| values[*a].total_cmp(&values[*b]))
+    }
+}
+
+/// Distances from every tile to the closest of the starts
+fn build(map: &Map, starts: &[usize]) -> Vec<f32> {
+    let mut dijkstra = DijkstraMap::new_empty(map.cols, map.rows, MAX_DEPTH);
+    // Building never writes the starts themselves, they would be left unreachable
+    starts.iter().for_each(|idx| dijkstra.map[*idx] = 0.0);
+    DijkstraMap::build(&mut dijkstra, starts, &Terrain(map));
+    dijkstra.map
+}
+
+fn build_flee(map: &Map, to_player: &[f32]) -> Vec<f32> {
+    let starts: Vec<(usize, f32)> = to_player
+        .iter()
+        .enumerate()
+        .filter(|(_, distance)| **distance < f32::MAX)
+        .map(|(idx, distance)| (idx, distance * FLEE_FACTOR))
+        .collect();
+
+    let mut dijkstra = DijkstraMap::new_empty(map.cols, map.rows, MAX_DEPTH);
+    starts
+        .iter()
+        .for_each(|(idx, weight)| dijkstra.map[*idx] = *weight);
+    DijkstraMap::build_weighted(&mut dijkstra, &starts, &Terrain(map));
+    dijkstra.map
+}
+
+pub fn update_dijkstra_maps(
+    mut dijkstra_maps: ResMut<DijkstraMaps>,
+    map: Res<Map>,
+    depth: Res<Depth>,
+    q_player: Query<&Position, With<Player>>,
+    q_items: Query<&Position, Or<(With<DoorKey>, With<Item>)>>,
+) {
+    let Ok(player_pos) = q_player.get_single() else {
+        return;
+    };
+
+    let map_revision = Some((depth.0, map.revision()));
+    let map_changed = dijkstra_maps.map_revision != map_revision;
+    let player = Some(map.xy_to_index(player_pos.x, player_pos.y));
+    let mut items: Vec<usize> = q_items
+        .iter()
+        .map(|pos| map.xy_to_index(pos.x, pos.y))
+        .collect();
+    items.sort_unstable();
+
+    if map_changed {
+        let stairs: Vec<usize> = map
+            .find_tile(Tile::DownStairs)
+            .map(|(x, y)| map.xy_to_index(x, y))
+            .into_iter()
+            .collect();
+        dijkstra_maps.to_stairs = build(&map, &stairs);
+        dijkstra_maps.map_revision = map_revision;
+    }
+
+    if map_changed || dijkstra_maps.player != player {
+        let to_player = build(&map, &[map.xy_to_index(player_pos.x, player_pos.y)]);
+        dijkstra_maps.flee = build_flee(&map, &to_player);
+        dijkstra_maps.to_player = to_player;
+        dijkstra_maps.player = player;
+    }
+
+    if map_changed || dijkstra_maps.items != items {
+        dijkstra_maps.to_items = build(&map, &items);
+        dijkstra_maps.items = items;
+    }
+}
+
+fn reset_dijkstra_maps(mut dijkstra_maps: ResMut<DijkstraMaps>) {
+    *dijkstra_maps = DijkstraMaps::default();
+}
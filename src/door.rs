@@ -0,0 +1,204 @@
+use bevy::prelude::*;
+
+use crate::actions::{set_movement_actions, Actions};
+use crate::item::Item;
+use crate::level::LevelStore;
+use crate::loading::TextureAssets;
+use crate::map::{spawn_map, Depth, Map, Position, RevealOnSight, Tile, Viewshed};
+use crate::player::{player_input, Player};
+use crate::{name_of, GameState};
+
+/// Name of the key entries in `Map::spawn_list`
+pub const KEY_NAME: &str = "Key";
+
+pub struct DoorPlugin;
+
+/// This plugin opens, closes and unlocks doors
+/// Keys lying on the floor are picked up when the player walks over them
+impl Plugin for DoorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<OperateDoor>()
+            .register_type::<KeyRing>()
+            .add_systems(OnEnter(GameState::Playing), spawn_keys.after(spawn_map))
+            .add_systems(OnExit(GameState::Playing), clear_keys)
+            .add_systems(
+                Update,
+                (
+                    close_doors.after(set_movement_actions),
+                    operate_doors.after(player_input),
+                    pickup_keys,
+                )
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+/// Sent when someone opens, closes or unlocks the door at the given tile
+#[derive(Event, Debug, Clone, Copy)]
+pub struct OperateDoor {
+    pub actor: Entity,
+    pub x: usize,
+    pub y: usize,
+}
+
+#[derive(Component)]
+pub struct DoorKey;
+
+/// Keys carried around, every one of them unlocks a single door
+#[derive(Component, Default, Debug, Clone, Reflect)]
+pub struct KeyRing {
+    pub keys: usize,
+}
+
+pub(crate) fn spawn_keys(
+    mut commands: Commands,
+    texture_assets: Res<TextureAssets>,
+    map: Res<Map>,
+    depth: Res<Depth>,
+    levels: Res<LevelStore>,
+) {
+    // Keys of a visited level are where the player left them
+    let keys: Vec<Position> = if let Some(level) = levels.get(&depth.0) {
+        level.keys.clone()
+    } else {
+        map.spawn_list
+            .iter()
+            .filter(|(_, name)| name == KEY_NAME)
+            .map(|(idx, _)| Position {
+                x: idx % map.cols,
+                y: idx / map.cols,
+            })
+            .collect()
+    };
+
+    keys.into_iter().for_each(|pos| {
+        commands.spawn((
+            SpriteSheetBundle {
+                transform: Transform::from_xyz(
+                    pos.x as f32 * map.tile_size as f32,
+                    pos.y as f32 * map.tile_size as f32,
+                    0.5,
+                ),
+                texture: texture_assets.map_atlas.clone(),
+                atlas: TextureAtlas {
+                    index: map.get_tile_index_in_sprite_sheet(32, 11),
+                    layout: texture_assets.map_atlas_layout.clone(),
+                },
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            Name::new(KEY_NAME),
+            DoorKey,
+            RevealOnSight,
+            pos,
+        ));
+    });
+}
+
+pub(crate) fn clear_keys(mut commands: Commands, q_keys: Query<Entity, With<DoorKey>>) {
+    q_keys.iter().for_each(|key| {
+        commands.entity(key).despawn_recursive();
+    });
+}
+
+fn close_doors(
+    actions: Res<Actions>,
+    map: Res<Map>,
+    q_player: Query<(Entity, &Position), With<Player>>,
+    mut ev_operate_door: EventWriter<OperateDoor>,
+) {
+    if !actions.close_door {
+        return;
+    }
+    let Ok((player_entity, pos)) = q_player.get_single() else {
+        return;
+    };
+
+    for y in pos.y.saturating_sub(1)..=(pos.y + 1).min(map.rows - 1) {
+        for x in pos.x.saturating_sub(1)..=(pos.x + 1).min(map.cols - 1) {
+            // The door the player stands in can't be closed
+            if (x, y) == (pos.x, pos.y) {
+                continue;
+            }
+            if map.get_tile(x, y) == Tile::DoorOpen {
+                ev_operate_door.send(OperateDoor {
+                    actor: player_entity,
+                    x,
+                    y,
+                });
+            }
+        }
+    }
+}
+
+fn operate_doors(
+    mut ev_operate_door: EventReader<OperateDoor>,
+    mut map: ResMut<Map>,
+    mut q_key_rings: Query<&mut KeyRing>,
+    q_names: Query<&Name>,
+    q_in_the_way: Query<&Position, Or<(With<Player>, With<Item>, With<DoorKey>)>>,
+    mut q_viewsheds: Query<&mut Viewshed>,
+) {
+    let mut changed = false;
+
+    for ev in ev_operate_door.read() {
+        let name = name_of(&q_names, ev.actor);
+        let idx = map.xy_to_index(ev.x, ev.y);
+
+        let tile = match map.get_tile(ev.x, ev.y) {
+            Tile::DoorClosed => Tile::DoorOpen,
+            Tile::DoorOpen => {
+                let blocked = !map.tile_content[idx].is_empty()
+                    || q_in_the_way
+                        .iter()
+                        .any(|pos| pos.x == ev.x && pos.y == ev.y);
+                if blocked {
+                    info!("Something is in the way of the door");
+                    continue;
+                }
+                Tile::DoorClosed
+            }
+            Tile::DoorLocked => match q_key_rings.get_mut(ev.actor) {
+                Ok(mut key_ring) if key_ring.keys > 0 => {
+                    key_ring.keys -= 1;
+                    info!("{} unlocks the door", name);
+                    Tile::DoorOpen
+                }
+                _ => {
+                    info!("The door is locked");
+                    continue;
+                }
+            },
+            _ => continue,
+        };
+
+        map.set_tile(ev.x, ev.y, tile);
+        changed = true;
+    }
+
+    // A door changes what everyone around can see
+    if changed {
+        q_viewsheds.iter_mut().for_each(|mut viewshed| {
+            viewshed.dirty = true;
+        });
+    }
+}
+
+fn pickup_keys(
+    mut commands: Commands,
+    mut q_player: Query<(&Position, &mut KeyRing), (With<Player>, Changed<Position>)>,
+    q_keys: Query<(Entity, &Position), With<DoorKey>>,
+) {
+    let Ok((player_pos, mut key_ring)) = q_player.get_single_mut() else {
+        return;
+    };
+
+    q_keys
+        .iter()
+        .filter(|(_, pos)| pos.x == player_pos.x && pos.y == player_pos.y)
+        .for_each(|(key, _)| {
+            key_ring.keys += 1;
+            info!("Picked up a key, {} carried", key_ring.keys);
+            commands.entity(key).despawn_recursive();
+        });
+}
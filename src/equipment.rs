@@ -0,0 +1,218 @@
+use bevy::{prelude::*, utils::hashbrown::HashMap};
+
+use crate::actions::{set_movement_actions, Actions};
+use crate::combat::melee_combat;
+use crate::item::InBackpack;
+use crate::player::Player;
+use crate::{name_of, GameState};
+
+pub struct EquipmentPlugin;
+
+/// This plugin puts on weapons and armor, one item per slot
+/// Whatever was worn in the slot before goes back into the backpack
+impl Plugin for EquipmentPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Equippable>()
+            .register_type::<Equipped>()
+            .register_type::<MeleePowerBonus>()
+            .register_type::<DefenseBonus>()
+            .register_type::<WantsToEquipItem>()
+            .register_type::<WantsToRemoveItem>()
+            .add_systems(
+                Update,
+                (
+                    equip_first.after(set_movement_actions),
+                    equip_items,
+                    remove_items,
+                )
+                    .chain()
+                    .before(melee_combat)
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub enum EquipmentSlot {
+    Melee,
+    Shield,
+    Armor,
+    Head,
+}
+
+impl EquipmentSlot {
+    pub const ALL: [EquipmentSlot; 4] = [
+        EquipmentSlot::Melee,
+        EquipmentSlot::Shield,
+        EquipmentSlot::Armor,
+        EquipmentSlot::Head,
+    ];
+}
+
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct Equippable {
+    pub slot: EquipmentSlot,
+}
+
+/// The item is worn by its owner instead of lying in the backpack
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct Equipped {
+    pub owner: Entity,
+    pub slot: EquipmentSlot,
+}
+
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct MeleePowerBonus {
+    pub power: i32,
+}
+
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct DefenseBonus {
+    pub defense: i32,
+}
+
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct WantsToEquipItem {
+    pub item: Entity,
+}
+
+/// Takes off a worn item and puts it in the backpack
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct WantsToRemoveItem {
+    pub item: Entity,
+}
+
+/// Power and defense added by everything the owner wears
+pub fn equipment_bonus(
+    owner: Entity,
+    q_equipment: &Query<(&Equipped, Option<&MeleePowerBonus>, Option<&DefenseBonus>)>,
+) -> (i32, i32) {
+    q_equipment
+        .iter()
+        .filter(|(equipped, _, _)| equipped.owner == owner)
+        .fold(
+            (0, 0),
+            |(power, defense), (_, power_bonus, defense_bonus)| {
+                (
+                    power + power_bonus.map_or(0, |bonus| bonus.power),
+                    defense + defense_bonus.map_or(0, |bonus| bonus.defense),
+                )
+            },
+        )
+}
+
+/// Puts on the first piece of equipment the player carries
+fn equip_first(
+    mut commands: Commands,
+    actions: Res<Actions>,
+    q_player: Query<Entity, With<Player>>,
+    q_equippables: Query<(Entity, &InBackpack), With<Equippable>>,
+) {
+    if !actions.equip {
+        return;
+    }
+    let Ok(player_entity) = q_player.get_single() else {
+        return;
+    };
+
+    match q_equippables
+        .iter()
+        .find(|(_, backpack)| backpack.owner == player_entity)
+    {
+        Some((item, _)) => {
+            commands.entity(player_entity).with_children(|parent| {
+                parent.spawn(WantsToEquipItem { item });
+            });
+        }
+        None => info!("You have nothing to equip"),
+    }
+}
+
+pub fn equip_items(
+    mut commands: Commands,
+    q_wants_to_equip: Query<(Entity, &Parent, &WantsToEquipItem)>,
+    q_equippables: Query<(&Name, &InBackpack, &Equippable)>,
+    q_equipped: Query<(Entity, &Equipped)>,
+    q_names: Query<&Name>,
+) {
+    // The queries don't see what was put on earlier in this run, so those slots are tracked here
+    let mut filled: HashMap<(Entity, EquipmentSlot), Entity> = HashMap::default();
+
+    for (entity, parent, wants_to_equip) in q_wants_to_equip.iter() {
+        commands.entity(entity).despawn_recursive();
+
+        let owner = parent.get();
+        // Only what is carried can be put on
+        let Ok((item_name, backpack, equippable)) = q_equippables.get(wants_to_equip.item) else {
+            continue;
+        };
+        if backpack.owner != owner {
+            continue;
+        }
+
+        let owner_name = name_of(&q_names, owner);
+
+        let previous: Vec<Entity> = match filled
+            .insert((owner, equippable.slot), wants_to_equip.item)
+        {
+            Some(previous) => vec![previous],
+            None => q_equipped
+                .iter()
+                .filter(|(_, equipped)| equipped.owner == owner && equipped.slot == equippable.slot)
+                .map(|(previous, ..)| previous)
+                .collect(),
+        };
+        previous
+            .into_iter()
+            .filter(|previous| *previous != wants_to_equip.item)
+            .for_each(|previous| {
+                commands
+                    .entity(previous)
+                    .remove::<Equipped>()
+                    .insert(InBackpack { owner });
+                info!(
+                    "{} puts the {} back in the backpack",
+                    owner_name,
+                    name_of(&q_names, previous)
+                );
+            });
+
+        commands
+            .entity(wants_to_equip.item)
+            .remove::<InBackpack>()
+            .insert(Equipped {
+                owner,
+                slot: equippable.slot,
+            });
+        info!("{} equips the {}", owner_name, item_name);
+    }
+}
+
+pub fn remove_items(
+    mut commands: Commands,
+    q_wants_to_remove: Query<(Entity, &Parent, &WantsToRemoveItem)>,
+    q_equipped: Query<(&Name, &Equipped)>,
+    q_names: Query<&Name>,
+) {
+    for (entity, parent, wants_to_remove) in q_wants_to_remove.iter() {
+        commands.entity(entity).despawn_recursive();
+
+        let owner = parent.get();
+        let Ok((item_name, equipped)) = q_equipped.get(wants_to_remove.item) else {
+            continue;
+        };
+        if equipped.owner != owner {
+            continue;
+        }
+
+        commands
+            .entity(wants_to_remove.item)
+            .remove::<Equipped>()
+            .insert(InBackpack { owner });
+        info!(
+            "{} puts the {} back in the backpack",
+            name_of(&q_names, owner),
+            item_name
+        );
+    }
+}
@@ -2,20 +2,62 @@ use bevy::prelude::*;
 use bevy::reflect::TypePath;
 use bevy::render::render_resource::*;
 
+use crate::actions::{set_menu_actions, set_movement_actions, Actions};
 use crate::combat::CombatStats;
+use crate::equipment::{
+    equipment_bonus, DefenseBonus, EquipmentSlot, Equipped, MeleePowerBonus, WantsToEquipItem,
+    WantsToRemoveItem,
+};
+use crate::item::{
+    InBackpack, Item, ItemDetails, ItemDetailsItem, WantsToDropItem, WantsToUseItem, THROW_RANGE,
+};
 use crate::loading::TextureAssets;
-use crate::map::{spawn_map, Map};
+use crate::map::{spawn_map, Depth, Map, Position};
 use crate::player::Player;
-use crate::{GameState, HUD_ROWS};
+use crate::rng::GameRng;
+use crate::targeting::Targeting;
+use crate::{GameState, PlayState, HUD_ROWS};
+
+/// Letters picking items on the inventory and equipment screens
+/// E and I switch between the screens, so they are left out
+const HOTKEYS: &[u8] = b"abcdfghjklmnopqrstuvwxyz";
+
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const DESCRIPTION_COLOR: Color = Color::rgb(0.6, 0.6, 0.6);
 
 pub struct GuiPlugin;
 impl Plugin for GuiPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(UiMaterialPlugin::<CustomUiMaterial>::default())
+            .init_resource::<ItemMenu>()
+            .add_event::<MenuInput>()
             .add_systems(OnEnter(GameState::Playing), setup_gui.after(spawn_map))
+            .add_systems(OnExit(GameState::Playing), clear_gui)
             .add_systems(
                 Update,
-                update_player_hp.run_if(in_state(GameState::Playing)),
+                (
+                    update_player_hp,
+                    update_depth_label,
+                    (
+                        open_item_screens
+                            .after(set_movement_actions)
+                            .run_if(in_state(PlayState::Running)),
+                        show_item_screens.run_if(state_changed::<PlayState>),
+                        (
+                            menu_keys.after(set_menu_actions),
+                            menu_clicks,
+                            handle_menu_input,
+                            draw_item_screens,
+                        )
+                            .chain()
+                            .run_if(
+                                in_state(PlayState::Inventory)
+                                    .or_else(in_state(PlayState::Equipment)),
+                            ),
+                    )
+                        .chain(),
+                )
+                    .run_if(in_state(GameState::Playing)),
             );
     }
 }
@@ -29,10 +71,63 @@ pub struct HudRoot;
 #[derive(Component, Default, Clone, Copy)]
 pub struct PlayerHpWidget;
 
+#[derive(Component, Default, Clone, Copy)]
+pub struct DepthLabel;
+
+/// Lists the items in the backpack
+#[derive(Component, Default, Clone, Copy)]
+pub struct InventoryScreen;
+
+/// Stats of the player and what they wear
+#[derive(Component, Default, Clone, Copy)]
+pub struct EquipmentScreen;
+
+/// The item picked on the inventory or equipment screen, if any
+#[derive(Resource, Default)]
+pub struct ItemMenu {
+    pub selected: Option<Entity>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemAction {
+    Use,
+    Equip,
+    Remove,
+    Drop,
+    Throw,
+}
+
+impl ItemAction {
+    fn key(self) -> char {
+        match self {
+            ItemAction::Use => 'u',
+            ItemAction::Equip => 'e',
+            ItemAction::Remove => 'r',
+            ItemAction::Drop => 'd',
+            ItemAction::Throw => 't',
+        }
+    }
+}
+
+/// What the player asked for on the item screens, by key or by click
+#[derive(Event, Debug, Clone, Copy)]
+pub enum MenuInput {
+    Select(Entity),
+    Act(ItemAction),
+    Back,
+    Open(PlayState),
+    Close,
+}
+
+/// Clicking the button is the same as pressing its key
+#[derive(Component, Clone, Copy)]
+pub struct MenuButton(MenuInput);
+
 fn setup_gui(
     mut commands: Commands,
     map: Res<Map>,
     texture_assets: Res<TextureAssets>,
+    rng: Res<GameRng>,
     mut ui_materials: ResMut<Assets<CustomUiMaterial>>,
 ) {
     commands
@@ -104,10 +199,73 @@ fn setup_gui(
                         PlayerHpWidget,
                         Name::new("Heart icon"),
                     ));
+                    child.spawn((
+                        TextBundle::from_section(
+                            "Depth: 1",
+                            TextStyle {
+                                font_size: 16.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                                ..default()
+                            },
+                        )
+                        .with_style(Style {
+                            margin: UiRect::left(Val::Auto),
+                            ..default()
+                        }),
+                        DepthLabel,
+                        Name::new("Depth label"),
+                    ));
+                    child.spawn((
+                        TextBundle::from_section(
+                            format!("Seed: {}", rng.seed()),
+                            TextStyle {
+                                font_size: 16.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                                ..default()
+                            },
+                        )
+                        .with_style(Style {
+                            margin: UiRect::horizontal(Val::Px(16.0)),
+                            ..default()
+                        }),
+                        Name::new("Seed label"),
+                    ));
                 });
+            spawn_item_screen(child, "InventoryScreen", InventoryScreen);
+            spawn_item_screen(child, "EquipmentScreen", EquipmentScreen);
         });
 }
 
+/// The screens start hidden and empty, they are filled when opened
+fn spawn_item_screen(parent: &mut ChildBuilder, name: &str, marker: impl Component) {
+    parent.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(8.0),
+                left: Val::Px(8.0),
+                min_width: Val::Px(320.0),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(8.0)),
+                border: UiRect::all(Val::Px(2.0)),
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.8).into(),
+            border_color: Color::WHITE.into(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        Name::new(name.to_string()),
+        marker,
+    ));
+}
+
+fn clear_gui(mut commands: Commands, q_root: Query<Entity, With<GuiRoot>>) {
+    q_root.iter().for_each(|root| {
+        commands.entity(root).despawn_recursive();
+    });
+}
+
 fn update_player_hp(
     player_stats_q: Query<&CombatStats, (With<Player>, Changed<CombatStats>)>,
     mut player_hp_widget_q: Query<&mut Style, With<PlayerHpWidget>>,
@@ -119,6 +277,394 @@ fn update_player_hp(
     }
 }
 
+fn update_depth_label(depth: Res<Depth>, mut q_label: Query<&mut Text, With<DepthLabel>>) {
+    if !depth.is_changed() {
+        return;
+    }
+    if let Ok(mut text) = q_label.get_single_mut() {
+        text.sections[0].value = format!("Depth: {}", depth.0);
+    }
+}
+
+fn open_item_screens(actions: Res<Actions>, mut next_state: ResMut<NextState<PlayState>>) {
+    if actions.toggle_inventory {
+        next_state.set(PlayState::Inventory);
+    } else if actions.toggle_equipment {
+        next_state.set(PlayState::Equipment);
+    }
+}
+
+/// Only the screen of the current state is shown, and it opens with nothing picked
+fn show_item_screens(
+    state: Res<State<PlayState>>,
+    mut menu: ResMut<ItemMenu>,
+    mut q_inventory: Query<&mut Visibility, (With<InventoryScreen>, Without<EquipmentScreen>)>,
+    mut q_equipment: Query<&mut Visibility, (With<EquipmentScreen>, Without<InventoryScreen>)>,
+) {
+    let visibility = |shown: bool| {
+        if shown {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        }
+    };
+    q_inventory.iter_mut().for_each(|mut v| {
+        *v = visibility(*state.get() == PlayState::Inventory);
+    });
+    q_equipment.iter_mut().for_each(|mut v| {
+        *v = visibility(*state.get() == PlayState::Equipment);
+    });
+    menu.selected = None;
+}
+
+/// Items on the screen of the given state, in hotkey order
+fn listed_items(
+    state: &PlayState,
+    player: Entity,
+    q_carried: &Query<(Entity, &Name, Option<&InBackpack>, Option<&Equipped>), With<Item>>,
+) -> Vec<Entity> {
+    match state {
+        PlayState::Equipment => EquipmentSlot::ALL
+            .iter()
+            .filter_map(|slot| {
+                q_carried
+                    .iter()
+                    .find(|(_, _, _, equipped)| {
+                        equipped.is_some_and(|equipped| {
+                            equipped.owner == player && equipped.slot == *slot
+                        })
+                    })
+                    .map(|(item, ..)| item)
+            })
+            .collect(),
+        _ => {
+            let mut items: Vec<(&str, Entity)> = q_carried
+                .iter()
+                .filter(|(_, _, backpack, _)| {
+                    backpack.is_some_and(|backpack| backpack.owner == player)
+                })
+                .map(|(item, name, ..)| (name.as_str(), item))
+                .collect();
+            items.sort();
+            items.into_iter().map(|(_, item)| item).collect()
+        }
+    }
+}
+
+/// What can be done with the item from the given screen
+fn item_actions(state: &PlayState, details: &ItemDetailsItem) -> Vec<ItemAction> {
+    match state {
+        PlayState::Equipment => vec![ItemAction::Remove, ItemAction::Drop],
+        _ => {
+            let mut actions = vec![];
+            if details.consumable {
+                actions.push(ItemAction::Use);
+            }
+            if details.equippable.is_some() {
+                actions.push(ItemAction::Equip);
+            }
+            actions.push(ItemAction::Drop);
+            actions.push(ItemAction::Throw);
+            actions
+        }
+    }
+}
+
+fn menu_keys(
+    actions: Res<Actions>,
+    state: Res<State<PlayState>>,
+    menu: Res<ItemMenu>,
+    q_player: Query<Entity, With<Player>>,
+    q_carried: Query<(Entity, &Name, Option<&InBackpack>, Option<&Equipped>), With<Item>>,
+    q_details: Query<ItemDetails>,
+    mut ev_menu_input: EventWriter<MenuInput>,
+) {
+    let state = state.get();
+
+    if let Some(item) = menu.selected {
+        if actions.cancel {
+            ev_menu_input.send(MenuInput::Back);
+        } else if let (Some(letter), Ok(details)) = (actions.menu_key, q_details.get(item)) {
+            if let Some(action) = item_actions(state, &details)
+                .into_iter()
+                .find(|action| action.key() == letter)
+            {
+                ev_menu_input.send(MenuInput::Act(action));
+            }
+        }
+        return;
+    }
+
+    let toggle = |screen: PlayState| {
+        if *state == screen {
+            MenuInput::Close
+        } else {
+            MenuInput::Open(screen)
+        }
+    };
+    if actions.toggle_inventory {
+        ev_menu_input.send(toggle(PlayState::Inventory));
+    } else if actions.toggle_equipment {
+        ev_menu_input.send(toggle(PlayState::Equipment));
+    } else if actions.cancel {
+        ev_menu_input.send(MenuInput::Close);
+    } else if let Some(letter) = actions.menu_key {
+        let Ok(player_entity) = q_player.get_single() else {
+            return;
+        };
+        let item = HOTKEYS
+            .iter()
+            .position(|hotkey| *hotkey as char == letter)
+            .and_then(|idx| {
+                listed_items(state, player_entity, &q_carried)
+                    .get(idx)
+                    .copied()
+            });
+        if let Some(item) = item {
+            ev_menu_input.send(MenuInput::Select(item));
+        }
+    }
+}
+
+fn menu_clicks(
+    q_buttons: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+    mut ev_menu_input: EventWriter<MenuInput>,
+) {
+    q_buttons
+        .iter()
+        .filter(|(interaction, _)| **interaction == Interaction::Pressed)
+        .for_each(|(_, button)| {
+            ev_menu_input.send(button.0);
+        });
+}
+
+fn handle_menu_input(
+    mut commands: Commands,
+    mut menu: ResMut<ItemMenu>,
+    mut next_state: ResMut<NextState<PlayState>>,
+    mut ev_menu_input: EventReader<MenuInput>,
+    q_player: Query<(Entity, &Position), With<Player>>,
+    q_details: Query<ItemDetails>,
+) {
+    let Ok((player_entity, player_pos)) = q_player.get_single() else {
+        return;
+    };
+
+    for input in ev_menu_input.read() {
+        match *input {
+            MenuInput::Select(item) => menu.selected = Some(item),
+            MenuInput::Back => menu.selected = None,
+            MenuInput::Open(screen) => next_state.set(screen),
+            MenuInput::Close => next_state.set(PlayState::Running),
+            MenuInput::Act(action) => {
+                let Some(item) = menu.selected else {
+                    continue;
+                };
+                let Ok(details) = q_details.get(item) else {
+                    continue;
+                };
+
+                // Ranged items and throwing need a target first
+                let targeting = |range: i32, radius: i32, throw: bool| Targeting {
+                    item,
+                    range,
+                    radius,
+                    cursor: *player_pos,
+                    throw,
+                };
+                match (action, details.ranged) {
+                    (ItemAction::Use, Some(ranged)) => {
+                        let radius = details.area_of_effect.map_or(0, |aoe| aoe.radius);
+                        commands.insert_resource(targeting(ranged.range, radius, false));
+                        next_state.set(PlayState::Targeting);
+                    }
+                    (ItemAction::Throw, _) => {
+                        commands.insert_resource(targeting(THROW_RANGE, 0, true));
+                        next_state.set(PlayState::Targeting);
+                    }
+                    (ItemAction::Use, None) => {
+                        spawn_intent(
+                            &mut commands,
+                            player_entity,
+                            WantsToUseItem { item, target: None },
+                        );
+                        next_state.set(PlayState::Running);
+                    }
+                    (ItemAction::Equip, _) => {
+                        spawn_intent(&mut commands, player_entity, WantsToEquipItem { item });
+                        next_state.set(PlayState::Running);
+                    }
+                    (ItemAction::Remove, _) => {
+                        spawn_intent(&mut commands, player_entity, WantsToRemoveItem { item });
+                        next_state.set(PlayState::Running);
+                    }
+                    (ItemAction::Drop, _) => {
+                        spawn_intent(&mut commands, player_entity, WantsToDropItem { item });
+                        next_state.set(PlayState::Running);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Intents are children of whoever acts
+fn spawn_intent(commands: &mut Commands, actor: Entity, intent: impl Bundle) {
+    commands.entity(actor).with_children(|parent| {
+        parent.spawn(intent);
+    });
+}
+
+fn menu_text(value: impl Into<String>, color: Color) -> TextSection {
+    TextSection::new(
+        value,
+        TextStyle {
+            font_size: 16.0,
+            color,
+            ..default()
+        },
+    )
+}
+
+fn spawn_menu_button(parent: &mut ChildBuilder, input: MenuInput, sections: Vec<TextSection>) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    padding: UiRect::vertical(Val::Px(2.0)),
+                    ..default()
+                },
+                background_color: Color::NONE.into(),
+                ..default()
+            },
+            MenuButton(input),
+        ))
+        .with_children(|button| {
+            button.spawn(TextBundle::from_sections(sections));
+        });
+}
+
+/// Fills the open screen with the list of items, or what can be done with the picked one
+#[allow(clippy::too_many_arguments)]
+fn draw_item_screens(
+    mut commands: Commands,
+    menu: Res<ItemMenu>,
+    state: Res<State<PlayState>>,
+    q_inventory: Query<Entity, With<InventoryScreen>>,
+    q_equipment_screen: Query<Entity, With<EquipmentScreen>>,
+    q_player: Query<(Entity, &CombatStats), With<Player>>,
+    q_carried: Query<(Entity, &Name, Option<&InBackpack>, Option<&Equipped>), With<Item>>,
+    q_details: Query<ItemDetails>,
+    q_equipment: Query<(&Equipped, Option<&MeleePowerBonus>, Option<&DefenseBonus>)>,
+) {
+    if !menu.is_changed() {
+        return;
+    }
+    let state = state.get();
+    let screen = match state {
+        PlayState::Equipment => q_equipment_screen.get_single(),
+        _ => q_inventory.get_single(),
+    };
+    let Ok(screen) = screen else {
+        return;
+    };
+    let Ok((player_entity, stats)) = q_player.get_single() else {
+        return;
+    };
+
+    commands.entity(screen).despawn_descendants();
+    commands.entity(screen).with_children(|parent| {
+        let title = match state {
+            PlayState::Equipment => {
+                let (power_bonus, defense_bonus) = equipment_bonus(player_entity, &q_equipment);
+                format!(
+                    "Equipment\n\nHp: {}/{}\nPower: {} ({:+})\nDefense: {} ({:+})\n",
+                    stats.hp,
+                    stats.max_hp,
+                    stats.power + power_bonus,
+                    power_bonus,
+                    stats.defense + defense_bonus,
+                    defense_bonus,
+                )
+            }
+            _ => "Inventory\n".to_string(),
+        };
+        parent.spawn(TextBundle::from_sections([menu_text(title, TEXT_COLOR)]));
+
+        // The picked item and what can be done with it
+        if let Some(details) = menu.selected.and_then(|item| q_details.get(item).ok()) {
+            parent.spawn(TextBundle::from_sections([
+                menu_text(format!("{}\n", details.name), TEXT_COLOR),
+                menu_text(format!("{}\n", details.description()), DESCRIPTION_COLOR),
+            ]));
+            for action in item_actions(state, &details) {
+                spawn_menu_button(
+                    parent,
+                    MenuInput::Act(action),
+                    vec![menu_text(
+                        format!("[{}] {:?}", action.key(), action),
+                        TEXT_COLOR,
+                    )],
+                );
+            }
+            spawn_menu_button(
+                parent,
+                MenuInput::Back,
+                vec![menu_text("[Esc] Back", TEXT_COLOR)],
+            );
+            return;
+        }
+
+        let items = listed_items(state, player_entity, &q_carried);
+        let row = |idx: usize, details: &ItemDetailsItem| {
+            vec![
+                menu_text(
+                    format!("{}) {}", HOTKEYS[idx] as char, details.name),
+                    TEXT_COLOR,
+                ),
+                menu_text(format!("  {}", details.description()), DESCRIPTION_COLOR),
+            ]
+        };
+        match state {
+            PlayState::Equipment => {
+                for slot in EquipmentSlot::ALL {
+                    let worn = items.iter().enumerate().find(|(_, item)| {
+                        q_carried
+                            .get(**item)
+                            .is_ok_and(|(.., equipped)| equipped.is_some_and(|e| e.slot == slot))
+                    });
+                    match worn.and_then(|(idx, item)| Some((idx, q_details.get(*item).ok()?))) {
+                        Some((idx, details)) if idx < HOTKEYS.len() => {
+                            let mut sections = vec![menu_text(format!("{:?}: ", slot), TEXT_COLOR)];
+                            sections.extend(row(idx, &details));
+                            spawn_menu_button(parent, MenuInput::Select(items[idx]), sections);
+                        }
+                        _ => {
+                            parent.spawn(TextBundle::from_sections([menu_text(
+                                format!("{:?}: -", slot),
+                                DESCRIPTION_COLOR,
+                            )]));
+                        }
+                    }
+                }
+            }
+            _ if items.is_empty() => {
+                parent.spawn(TextBundle::from_sections([menu_text(
+                    "The backpack is empty",
+                    DESCRIPTION_COLOR,
+                )]));
+            }
+            _ => {
+                for (idx, item) in items.iter().enumerate().take(HOTKEYS.len()) {
+                    if let Ok(details) = q_details.get(*item) {
+                        spawn_menu_button(parent, MenuInput::Select(*item), row(idx, &details));
+                    }
+                }
+            }
+        }
+    });
+}
+
 #[derive(ShaderType, Debug, Clone)]
 struct AtlasTiled {
     atlas_grids: Vec2,
@@ -0,0 +1,779 @@
+use bevy::ecs::query::QueryData;
+use bevy::prelude::*;
+use bracket_pathfinding::prelude::*;
+use rand::Rng;
+
+use crate::actions::{set_movement_actions, Actions};
+use crate::combat::{apply_damage, delete_the_dead, melee_combat, CombatStats, SufferDamage};
+use crate::equipment::{DefenseBonus, EquipmentSlot, Equippable, Equipped, MeleePowerBonus};
+use crate::level::LevelStore;
+use crate::loading::TextureAssets;
+use crate::map::{spawn_map, Depth, Map, Position, RevealOnSight};
+use crate::monster::{spawn_monster, Confused, Monster};
+use crate::player::Player;
+use crate::rng::GameRng;
+use crate::targeting::Targeting;
+use crate::{name_of, GameState, PlayState};
+
+/// How far items can be thrown
+pub const THROW_RANGE: i32 = 6;
+
+/// Damage done by a thrown item, weapons add their power bonus
+const THROW_DAMAGE: i32 = 1;
+
+/// Chance for every spawn region to get an item lying around
+const ITEM_CHANCE: f64 = 0.5;
+
+/// Items that can be found lying around and how common they are
+const ITEM_TABLE: &[(&str, u32)] = &[
+    ("Health Potion", 4),
+    ("Magic Missile Scroll", 3),
+    ("Confusion Scroll", 2),
+    ("Fireball Scroll", 1),
+    ("Dagger", 2),
+    ("Longsword", 1),
+    ("Shield", 2),
+    ("Tower Shield", 1),
+    ("Leather Armor", 2),
+    ("Helmet", 2),
+];
+
+pub struct ItemPlugin;
+
+/// This plugin places items on the map, lets them be picked up and used
+/// Item logic is only active during the State `GameState::Playing`
+impl Plugin for ItemPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<InBackpack>()
+            .register_type::<WantsToPickupItem>()
+            .register_type::<WantsToUseItem>()
+            .register_type::<WantsToDropItem>()
+            .register_type::<WantsToThrowItem>()
+            .register_type::<ProvidesHealing>()
+            .register_type::<Ranged>()
+            .register_type::<InflictsDamage>()
+            .register_type::<AreaOfEffect>()
+            .register_type::<Confusion>()
+            .add_systems(
+                OnEnter(GameState::Playing),
+                spawn_items.after(spawn_map).before(spawn_monster),
+            )
+            .add_systems(OnExit(GameState::Playing), clear_backpacks)
+            .add_systems(
+                Update,
+                (
+                    (
+                        pickup_item.after(set_movement_actions),
+                        drink_potion.after(set_movement_actions),
+                        read_scroll.after(set_movement_actions),
+                        item_collection,
+                        drop_items,
+                        throw_items,
+                        use_items.before(melee_combat),
+                    )
+                        .chain(),
+                    drop_backpacks.after(apply_damage).before(delete_the_dead),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+#[derive(Component)]
+pub struct Item;
+
+/// The item is carried around by its owner instead of lying on the map
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct InBackpack {
+    pub owner: Entity,
+}
+
+/// The item is gone once used
+#[derive(Component)]
+pub struct Consumable;
+
+/// Using the item restores hit points, up to the maximum
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct ProvidesHealing {
+    pub heal_amount: i32,
+}
+
+/// The item is used on a tile picked by its user, at most this far away
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct Ranged {
+    pub range: i32,
+}
+
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct InflictsDamage {
+    pub damage: i32,
+}
+
+/// Everything around the target is hit as well
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct AreaOfEffect {
+    pub radius: i32,
+}
+
+/// Monsters hit lose their next turns
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct Confusion {
+    pub turns: i32,
+}
+
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct WantsToPickupItem {
+    pub item: Entity,
+}
+
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct WantsToUseItem {
+    pub item: Entity,
+    /// Where a ranged item is used, the user is the target otherwise
+    pub target: Option<Position>,
+}
+
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct WantsToDropItem {
+    pub item: Entity,
+}
+
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct WantsToThrowItem {
+    pub item: Entity,
+    pub target: Position,
+}
+
+/// Everything that tells what an item does
+#[derive(QueryData)]
+pub struct ItemDetails {
+    pub name: &'static Name,
+    pub healing: Option<&'static ProvidesHealing>,
+    pub damage: Option<&'static InflictsDamage>,
+    pub area_of_effect: Option<&'static AreaOfEffect>,
+    pub confusion: Option<&'static Confusion>,
+    pub ranged: Option<&'static Ranged>,
+    pub equippable: Option<&'static Equippable>,
+    pub power_bonus: Option<&'static MeleePowerBonus>,
+    pub defense_bonus: Option<&'static DefenseBonus>,
+    pub consumable: Has<Consumable>,
+}
+
+impl ItemDetailsItem<'_> {
+    pub fn description(&self) -> String {
+        let mut parts = vec![];
+        if let Some(healing) = self.healing {
+            parts.push(format!("restores {} hp", healing.heal_amount));
+        }
+        if let Some(damage) = self.damage {
+            parts.push(format!("deals {} damage", damage.damage));
+        }
+        if let Some(area_of_effect) = self.area_of_effect {
+            parts.push(format!(
+                "hits everything within {} tiles",
+                area_of_effect.radius
+            ));
+        }
+        if let Some(confusion) = self.confusion {
+            parts.push(format!("confuses for {} turns", confusion.turns));
+        }
+        if let Some(ranged) = self.ranged {
+            parts.push(format!("range {}", ranged.range));
+        }
+        if let Some(equippable) = self.equippable {
+            parts.push(format!("worn in the {:?} slot", equippable.slot));
+        }
+        if let Some(bonus) = self.power_bonus {
+            parts.push(format!("+{} power", bonus.power));
+        }
+        if let Some(bonus) = self.defense_bonus {
+            parts.push(format!("+{} defense", bonus.defense));
+        }
+        if self.consumable {
+            parts.push("single use".to_string());
+        }
+
+        let mut description = parts.join(", ");
+        if let Some(first) = description.get_mut(0..1) {
+            first.make_ascii_uppercase();
+        }
+        description
+    }
+}
+
+/// Puts a carried or worn item on the map
+fn place_item(commands: &mut Commands, map: &Map, item: Entity, pos: Position) {
+    let visibility = if map.revealed_tiles[map.xy_to_index(pos.x, pos.y)] {
+        Visibility::Visible
+    } else {
+        Visibility::Hidden
+    };
+    commands
+        .entity(item)
+        .remove::<(InBackpack, Equipped)>()
+        .insert((
+            Transform::from_xyz(
+                pos.x as f32 * map.tile_size as f32,
+                pos.y as f32 * map.tile_size as f32,
+                0.5,
+            ),
+            pos,
+            visibility,
+        ));
+}
+
+/// An item as it was when the player left its level
+pub struct StoredItem {
+    pub name: String,
+    pub position: Position,
+}
+
+pub(crate) fn spawn_items(
+    mut commands: Commands,
+    texture_assets: Res<TextureAssets>,
+    map: Res<Map>,
+    depth: Res<Depth>,
+    levels: Res<LevelStore>,
+    mut rng: ResMut<GameRng>,
+) {
+    // Items of a visited level are where the player left them
+    if let Some(level) = levels.get(&depth.0) {
+        level.items.iter().for_each(|item| {
+            spawn_item_at(
+                &mut commands,
+                &texture_assets,
+                &map,
+                item.position,
+                &item.name,
+            );
+        });
+        return;
+    }
+
+    map.spawn_regions.iter().for_each(|region| {
+        if !rng.gen_bool(ITEM_CHANCE) {
+            return;
+        }
+        let idx = region[rng.gen_range(0..region.len())];
+        let name = random_item(&mut rng);
+        spawn_item_at(
+            &mut commands,
+            &texture_assets,
+            &map,
+            Position {
+                x: idx % map.cols,
+                y: idx / map.cols,
+            },
+            name,
+        );
+    });
+
+    // Items placed by prefabs
+    map.spawn_list.iter().for_each(|(idx, name)| {
+        spawn_item_at(
+            &mut commands,
+            &texture_assets,
+            &map,
+            Position {
+                x: idx % map.cols,
+                y: idx / map.cols,
+            },
+            name,
+        );
+    });
+}
+
+fn random_item(rng: &mut GameRng) -> &'static str {
+    let total: u32 = ITEM_TABLE.iter().map(|(_, weight)| weight).sum();
+    let mut roll = rng.gen_range(0..total);
+    for (name, weight) in ITEM_TABLE {
+        if roll < *weight {
+            return name;
+        }
+        roll -= weight;
+    }
+    ITEM_TABLE[0].0
+}
+
+/// Sprite position in the sheet of the named items
+fn item_sprite(name: &str) -> Option<(usize, usize)> {
+    match name {
+        "Health Potion" => Some((34, 13)),
+        "Magic Missile Scroll" => Some((33, 15)),
+        "Fireball Scroll" => Some((34, 15)),
+        "Confusion Scroll" => Some((32, 15)),
+        "Dagger" => Some((32, 6)),
+        "Longsword" => Some((34, 8)),
+        "Shield" => Some((37, 3)),
+        "Tower Shield" => Some((38, 4)),
+        "Leather Armor" => Some((37, 1)),
+        "Helmet" => Some((33, 0)),
+        _ => None,
+    }
+}
+
+/// Spawns the named item lying on the map, nothing happens for names that aren't items
+pub(crate) fn spawn_item_at(
+    commands: &mut Commands,
+    texture_assets: &TextureAssets,
+    map: &Map,
+    pos: Position,
+    name: &str,
+) -> Option<Entity> {
+    let item = spawn_item(commands, texture_assets, map, name)?;
+    commands.entity(item).insert((
+        Transform::from_xyz(
+            pos.x as f32 * map.tile_size as f32,
+            pos.y as f32 * map.tile_size as f32,
+            0.5,
+        ),
+        pos,
+    ));
+    Some(item)
+}
+
+/// Spawns the named item carried by its owner, nothing happens for names that aren't items
+pub(crate) fn spawn_item_in_backpack(
+    commands: &mut Commands,
+    texture_assets: &TextureAssets,
+    map: &Map,
+    owner: Entity,
+    name: &str,
+) -> Option<Entity> {
+    let item = spawn_item(commands, texture_assets, map, name)?;
+    commands.entity(item).insert(InBackpack { owner });
+    Some(item)
+}
+
+/// The item itself, hidden until it is placed on the map
+fn spawn_item(
+    commands: &mut Commands,
+    texture_assets: &TextureAssets,
+    map: &Map,
+    name: &str,
+) -> Option<Entity> {
+    let sprite = item_sprite(name)?;
+
+    let mut item = commands.spawn((
+        SpriteSheetBundle {
+            texture: texture_assets.map_atlas.clone(),
+            atlas: TextureAtlas {
+                index: map.get_tile_index_in_sprite_sheet(sprite.0, sprite.1),
+                layout: texture_assets.map_atlas_layout.clone(),
+            },
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        Name::new(name.to_string()),
+        Item,
+        RevealOnSight,
+    ));
+
+    match name {
+        "Health Potion" => {
+            item.insert((Consumable, ProvidesHealing { heal_amount: 8 }));
+        }
+        "Magic Missile Scroll" => {
+            item.insert((
+                Consumable,
+                Ranged { range: 6 },
+                InflictsDamage { damage: 8 },
+            ));
+        }
+        "Fireball Scroll" => {
+            item.insert((
+                Consumable,
+                Ranged { range: 6 },
+                InflictsDamage { damage: 20 },
+                AreaOfEffect { radius: 3 },
+            ));
+        }
+        "Confusion Scroll" => {
+            item.insert((Consumable, Ranged { range: 6 }, Confusion { turns: 4 }));
+        }
+        "Dagger" => {
+            item.insert((
+                Equippable {
+                    slot: EquipmentSlot::Melee,
+                },
+                MeleePowerBonus { power: 2 },
+            ));
+        }
+        "Longsword" => {
+            item.insert((
+                Equippable {
+                    slot: EquipmentSlot::Melee,
+                },
+                MeleePowerBonus { power: 4 },
+            ));
+        }
+        "Shield" => {
+            item.insert((
+                Equippable {
+                    slot: EquipmentSlot::Shield,
+                },
+                DefenseBonus { defense: 1 },
+            ));
+        }
+        "Tower Shield" => {
+            item.insert((
+                Equippable {
+                    slot: EquipmentSlot::Shield,
+                },
+                DefenseBonus { defense: 3 },
+            ));
+        }
+        "Leather Armor" => {
+            item.insert((
+                Equippable {
+                    slot: EquipmentSlot::Armor,
+                },
+                DefenseBonus { defense: 1 },
+            ));
+        }
+        "Helmet" => {
+            item.insert((
+                Equippable {
+                    slot: EquipmentSlot::Head,
+                },
+                DefenseBonus { defense: 1 },
+            ));
+        }
+        _ => {}
+    }
+
+    Some(item.id())
+}
+
+/// Removes the items of the level, the ones the player carries or wears go along with them
+pub(crate) fn clear_items(
+    mut commands: Commands,
+    q_items: Query<(Entity, Option<&InBackpack>, Option<&Equipped>), With<Item>>,
+    q_player: Query<(), With<Player>>,
+) {
+    q_items
+        .iter()
+        .filter(|(_, backpack, equipped)| {
+            let owner = backpack
+                .map(|backpack| backpack.owner)
+                .or(equipped.map(|equipped| equipped.owner));
+            owner.is_none_or(|owner| !q_player.contains(owner))
+        })
+        .for_each(|(item, _, _)| {
+            commands.entity(item).despawn_recursive();
+        });
+}
+
+fn clear_backpacks(mut commands: Commands, q_items: Query<Entity, With<Item>>) {
+    q_items.iter().for_each(|item| {
+        commands.entity(item).despawn_recursive();
+    });
+}
+
+fn pickup_item(
+    mut commands: Commands,
+    actions: Res<Actions>,
+    q_player: Query<(Entity, &Position), With<Player>>,
+    q_items: Query<(Entity, &Position), With<Item>>,
+) {
+    if !actions.pickup {
+        return;
+    }
+    let Ok((player_entity, player_pos)) = q_player.get_single() else {
+        return;
+    };
+
+    match q_items
+        .iter()
+        .find(|(_, pos)| pos.x == player_pos.x && pos.y == player_pos.y)
+    {
+        Some((item, _)) => {
+            commands.entity(player_entity).with_children(|parent| {
+                parent.spawn(WantsToPickupItem { item });
+            });
+        }
+        None => info!("There is nothing here to pick up"),
+    }
+}
+
+/// Drinks the first healing potion the player carries
+fn drink_potion(
+    mut commands: Commands,
+    actions: Res<Actions>,
+    q_player: Query<Entity, With<Player>>,
+    q_potions: Query<(Entity, &InBackpack), With<ProvidesHealing>>,
+) {
+    if !actions.drink {
+        return;
+    }
+    let Ok(player_entity) = q_player.get_single() else {
+        return;
+    };
+
+    match q_potions
+        .iter()
+        .find(|(_, backpack)| backpack.owner == player_entity)
+    {
+        Some((item, _)) => {
+            commands.entity(player_entity).with_children(|parent| {
+                parent.spawn(WantsToUseItem { item, target: None });
+            });
+        }
+        None => info!("You have no potion to drink"),
+    }
+}
+
+/// Reads the first scroll the player carries, once its target is picked
+fn read_scroll(
+    mut commands: Commands,
+    actions: Res<Actions>,
+    mut next_state: ResMut<NextState<PlayState>>,
+    q_player: Query<(Entity, &Position), With<Player>>,
+    q_scrolls: Query<(Entity, &InBackpack, &Ranged, Option<&AreaOfEffect>)>,
+) {
+    if !actions.read {
+        return;
+    }
+    let Ok((player_entity, player_pos)) = q_player.get_single() else {
+        return;
+    };
+
+    match q_scrolls
+        .iter()
+        .find(|(_, backpack, _, _)| backpack.owner == player_entity)
+    {
+        Some((item, _, ranged, area_of_effect)) => {
+            commands.insert_resource(Targeting {
+                item,
+                range: ranged.range,
+                radius: area_of_effect.map_or(0, |aoe| aoe.radius),
+                cursor: *player_pos,
+                throw: false,
+            });
+            next_state.set(PlayState::Targeting);
+        }
+        None => info!("You have no scroll to read"),
+    }
+}
+
+pub fn item_collection(
+    mut commands: Commands,
+    q_wants_to_pickup: Query<(Entity, &Parent, &WantsToPickupItem)>,
+    q_items: Query<&Name, (With<Item>, With<Position>)>,
+    q_names: Query<&Name>,
+) {
+    for (entity, parent, wants_to_pickup) in q_wants_to_pickup.iter() {
+        commands.entity(entity).despawn_recursive();
+
+        // Someone else got to it first
+        let Ok(item_name) = q_items.get(wants_to_pickup.item) else {
+            continue;
+        };
+
+        let owner = parent.get();
+        commands
+            .entity(wants_to_pickup.item)
+            .remove::<Position>()
+            .insert((InBackpack { owner }, Visibility::Hidden));
+
+        info!("{} picks up the {}", name_of(&q_names, owner), item_name);
+    }
+}
+
+pub fn use_items(
+    mut commands: Commands,
+    map: Res<Map>,
+    q_wants_to_use: Query<(Entity, &Parent, &WantsToUseItem)>,
+    q_items: Query<(
+        &Name,
+        &InBackpack,
+        Option<&ProvidesHealing>,
+        Option<&InflictsDamage>,
+        Option<&AreaOfEffect>,
+        Option<&Confusion>,
+        Has<Ranged>,
+        Has<Consumable>,
+    )>,
+    q_names: Query<&Name>,
+    mut q_targets: Query<(Entity, &Name, &Position, &mut CombatStats, Has<Monster>)>,
+) {
+    for (entity, parent, wants_to_use) in q_wants_to_use.iter() {
+        commands.entity(entity).despawn_recursive();
+
+        let user = parent.get();
+        // Only what is carried can be used, and only by whoever carries it
+        let Ok((
+            item_name,
+            backpack,
+            healing,
+            damage,
+            area_of_effect,
+            confusion,
+            ranged,
+            consumable,
+        )) = q_items.get(wants_to_use.item)
+        else {
+            continue;
+        };
+        if backpack.owner != user {
+            continue;
+        }
+
+        let targets: Vec<Entity> = match wants_to_use.target {
+            Some(target) => {
+                let tiles: Vec<Point> = match area_of_effect {
+                    Some(area_of_effect) => {
+                        field_of_view(target.into(), area_of_effect.radius, &*map)
+                            .into_iter()
+                            .filter(|pos| map.in_bounds(*pos))
+                            .collect()
+                    }
+                    None => vec![target.into()],
+                };
+                q_targets
+                    .iter()
+                    .filter(|(_, _, pos, ..)| tiles.contains(&Point::from(*pos)))
+                    .map(|(target, ..)| target)
+                    .collect()
+            }
+            None if ranged => {
+                info!("The {} needs a target", item_name);
+                continue;
+            }
+            None => vec![user],
+        };
+
+        let user_name = name_of(&q_names, user);
+        if ranged {
+            info!("{} reads the {}", user_name, item_name);
+        } else if wants_to_use.target.is_none() {
+            info!("{} drinks the {}", user_name, item_name);
+        }
+
+        for target in targets {
+            let Ok((_, target_name, _, mut stats, is_monster)) = q_targets.get_mut(target) else {
+                continue;
+            };
+
+            if let Some(healing) = healing {
+                let healed = healing.heal_amount.min(stats.max_hp - stats.hp).max(0);
+                stats.hp += healed;
+                info!("{} is healed for {} hp", target_name, healed);
+            }
+
+            if let Some(damage) = damage {
+                info!("{} is hit for {} hp", target_name, damage.damage);
+                SufferDamage::new_damage(&mut commands, target, damage.damage);
+            }
+
+            if let Some(confusion) = confusion {
+                if is_monster {
+                    info!("{} is confused", target_name);
+                    commands.entity(target).insert(Confused {
+                        turns: confusion.turns,
+                    });
+                }
+            }
+        }
+
+        if consumable {
+            commands.entity(wants_to_use.item).despawn_recursive();
+        }
+    }
+}
+
+pub fn drop_items(
+    mut commands: Commands,
+    map: Res<Map>,
+    q_wants_to_drop: Query<(Entity, &Parent, &WantsToDropItem)>,
+    q_items: Query<(&Name, Option<&InBackpack>, Option<&Equipped>)>,
+    q_owners: Query<(&Name, &Position)>,
+) {
+    for (entity, parent, wants_to_drop) in q_wants_to_drop.iter() {
+        commands.entity(entity).despawn_recursive();
+
+        let owner = parent.get();
+        let Ok((item_name, backpack, equipped)) = q_items.get(wants_to_drop.item) else {
+            continue;
+        };
+        let carried = backpack.is_some_and(|backpack| backpack.owner == owner)
+            || equipped.is_some_and(|equipped| equipped.owner == owner);
+        if !carried {
+            continue;
+        }
+        let Ok((owner_name, pos)) = q_owners.get(owner) else {
+            continue;
+        };
+
+        place_item(&mut commands, &map, wants_to_drop.item, *pos);
+        info!("{} drops the {}", owner_name, item_name);
+    }
+}
+
+pub fn throw_items(
+    mut commands: Commands,
+    map: Res<Map>,
+    q_wants_to_throw: Query<(Entity, &Parent, &WantsToThrowItem)>,
+    q_items: Query<(
+        &Name,
+        &InBackpack,
+        Option<&MeleePowerBonus>,
+        Has<ProvidesHealing>,
+    )>,
+    q_names: Query<&Name>,
+    q_targets: Query<(Entity, &Name, &Position), With<CombatStats>>,
+) {
+    for (entity, parent, wants_to_throw) in q_wants_to_throw.iter() {
+        commands.entity(entity).despawn_recursive();
+
+        let thrower = parent.get();
+        let Ok((item_name, backpack, power_bonus, healing)) = q_items.get(wants_to_throw.item)
+        else {
+            continue;
+        };
+        if backpack.owner != thrower {
+            continue;
+        }
+        info!("{} throws the {}", name_of(&q_names, thrower), item_name);
+
+        let target = wants_to_throw.target;
+        // Potions break and whoever they hit gets what is inside
+        if healing {
+            commands.entity(thrower).with_children(|parent| {
+                parent.spawn(WantsToUseItem {
+                    item: wants_to_throw.item,
+                    target: Some(target),
+                });
+            });
+            continue;
+        }
+
+        place_item(&mut commands, &map, wants_to_throw.item, target);
+        let damage = THROW_DAMAGE + power_bonus.map_or(0, |bonus| bonus.power);
+        if let Some((hit, hit_name, _)) = q_targets
+            .iter()
+            .find(|(_, _, pos)| pos.x == target.x && pos.y == target.y)
+        {
+            info!("{} is hit for {} hp", hit_name, damage);
+            SufferDamage::new_damage(&mut commands, hit, damage);
+        }
+    }
+}
+
+/// The dead leave what they carried where they fell
+fn drop_backpacks(
+    mut commands: Commands,
+    map: Res<Map>,
+    q_dead: Query<(Entity, &CombatStats, &Position)>,
+    q_items: Query<(Entity, &InBackpack)>,
+) {
+    q_dead
+        .iter()
+        .filter(|(_, stats, _)| stats.hp <= 0)
+        .for_each(|(owner, _, pos)| {
+            q_items
+                .iter()
+                .filter(|(_, backpack)| backpack.owner == owner)
+                .for_each(|(item, _)| place_item(&mut commands, &map, item, *pos));
+        });
+}
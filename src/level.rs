@@ -0,0 +1,198 @@
+use bevy::{prelude::*, utils::hashbrown::HashMap};
+
+use crate::actions::{set_movement_actions, Actions};
+use crate::combat::CombatStats;
+use crate::door::{clear_keys, spawn_keys, DoorKey};
+use crate::item::{clear_items, spawn_items, InBackpack, Item, StoredItem};
+use crate::map::{clear_map, spawn_map, Depth, Map, Position, Tile, Viewshed};
+use crate::monster::{clear_monster, spawn_monster, Monster};
+use crate::player::Player;
+use crate::GameState;
+
+pub struct LevelPlugin;
+
+/// This plugin moves the player between dungeon levels
+/// The current level is stored away and the next one is restored or generated, the player entity is kept
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LevelStore>()
+            .add_event::<ChangeLevel>()
+            .add_systems(OnExit(GameState::Playing), reset_levels)
+            .add_systems(
+                Update,
+                (
+                    try_change_level.after(set_movement_actions),
+                    (
+                        store_level,
+                        clear_monster,
+                        clear_keys,
+                        clear_items,
+                        clear_map,
+                        apply_deferred,
+                        spawn_map,
+                        spawn_keys,
+                        spawn_items,
+                        spawn_monster,
+                        forget_level,
+                        place_player,
+                    )
+                        .chain()
+                        .run_if(on_event::<ChangeLevel>()),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+#[derive(Event, Clone, Copy, PartialEq, Debug)]
+pub enum ChangeLevel {
+    Descend,
+    Ascend,
+}
+
+/// A monster as it was when the player left its level
+pub struct StoredMonster {
+    pub name: String,
+    pub stats: CombatStats,
+    pub position: Position,
+    pub sprite: usize,
+    /// Names of the items carried
+    pub items: Vec<String>,
+}
+
+pub struct LevelSnapshot {
+    pub map: Map,
+    pub monsters: Vec<StoredMonster>,
+    pub keys: Vec<Position>,
+    pub items: Vec<StoredItem>,
+}
+
+/// Levels the player has visited, keyed by depth
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct LevelStore(HashMap<usize, LevelSnapshot>);
+
+fn try_change_level(
+    actions: Res<Actions>,
+    map: Res<Map>,
+    q_player: Query<&Position, With<Player>>,
+    mut ev_change_level: EventWriter<ChangeLevel>,
+) {
+    if !actions.descend && !actions.ascend {
+        return;
+    }
+    let Ok(pos) = q_player.get_single() else {
+        return;
+    };
+
+    match map.get_tile(pos.x, pos.y) {
+        Tile::DownStairs if actions.descend => {
+            ev_change_level.send(ChangeLevel::Descend);
+        }
+        Tile::UpStairs if actions.ascend => {
+            ev_change_level.send(ChangeLevel::Ascend);
+        }
+        _ if actions.descend => info!("There is no way down from here"),
+        _ => info!("There is no way up from here"),
+    }
+}
+
+/// Keeps the level being left so it can be restored when the player comes back
+#[allow(clippy::too_many_arguments)]
+fn store_level(
+    mut levels: ResMut<LevelStore>,
+    mut depth: ResMut<Depth>,
+    map: Res<Map>,
+    mut ev_change_level: EventReader<ChangeLevel>,
+    q_monsters: Query<(Entity, &Name, &CombatStats, &Position, &TextureAtlas), With<Monster>>,
+    q_keys: Query<&Position, With<DoorKey>>,
+    q_items: Query<(&Name, &Position), With<Item>>,
+    q_backpacks: Query<(&Name, &InBackpack)>,
+) {
+    let Some(change) = ev_change_level.read().last().copied() else {
+        return;
+    };
+
+    let monsters = q_monsters
+        .iter()
+        .filter(|(_, _, stats, _, _)| stats.hp > 0)
+        .map(|(entity, name, stats, pos, atlas)| StoredMonster {
+            name: name.to_string(),
+            stats: stats.clone(),
+            position: *pos,
+            sprite: atlas.index,
+            items: q_backpacks
+                .iter()
+                .filter(|(_, backpack)| backpack.owner == entity)
+                .map(|(name, _)| name.to_string())
+                .collect(),
+        })
+        .collect();
+
+    let mut map = map.clone();
+    map.visible_tiles.fill(false);
+    map.tile_content
+        .iter_mut()
+        .for_each(|content| content.clear());
+    let keys = q_keys.iter().copied().collect();
+    // Items carried around have no position and leave with their owner
+    let items = q_items
+        .iter()
+        .map(|(name, pos)| StoredItem {
+            name: name.to_string(),
+            position: *pos,
+        })
+        .collect();
+    levels.insert(
+        depth.0,
+        LevelSnapshot {
+            map,
+            monsters,
+            keys,
+            items,
+        },
+    );
+
+    match change {
+        ChangeLevel::Descend => depth.0 += 1,
+        ChangeLevel::Ascend => depth.0 -= 1,
+    }
+    info!("Moving to depth {}", depth.0);
+}
+
+/// Drops the snapshot of the level just restored, it is taken again when the player leaves
+fn forget_level(mut levels: ResMut<LevelStore>, depth: Res<Depth>) {
+    levels.remove(&depth.0);
+}
+
+/// Moves the player onto the stairs they arrived by
+fn place_player(
+    map: Res<Map>,
+    mut ev_change_level: EventReader<ChangeLevel>,
+    mut q_player: Query<(&mut Transform, &mut Position, &mut Viewshed), With<Player>>,
+) {
+    let Some(change) = ev_change_level.read().last().copied() else {
+        return;
+    };
+    let Ok((mut transform, mut pos, mut viewshed)) = q_player.get_single_mut() else {
+        return;
+    };
+
+    let (x, y) = match change {
+        ChangeLevel::Descend => map.player_start,
+        ChangeLevel::Ascend => map.find_tile(Tile::DownStairs).unwrap_or(map.player_start),
+    };
+    pos.x = x;
+    pos.y = y;
+    transform.translation = Vec3::new(
+        pos.x as f32 * map.tile_size as f32,
+        pos.y as f32 * map.tile_size as f32,
+        1.0,
+    );
+    viewshed.dirty = true;
+}
+
+fn reset_levels(mut depth: ResMut<Depth>, mut levels: ResMut<LevelStore>) {
+    depth.0 = 1;
+    levels.clear();
+}
@@ -2,13 +2,24 @@
 
 mod actions;
 mod audio;
+mod camera;
 mod combat;
+mod dijkstra;
+mod door;
+mod equipment;
 mod gui;
-mod loading;
-mod map;
+mod item;
+mod level;
+pub mod loading;
+pub mod map;
+mod map_builder;
 mod menu;
+mod minimap;
 mod monster;
 mod player;
+mod rng;
+mod targeting;
+pub mod tilemap;
 
 use std::time::Duration;
 
@@ -23,10 +34,20 @@ use bevy::app::App;
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
 use bevy::prelude::*;
 use bevy::time::common_conditions::on_timer;
+use camera::CameraPlugin;
 use combat::CombatPlugin;
+use dijkstra::DijkstraPlugin;
+use door::DoorPlugin;
+use equipment::EquipmentPlugin;
 use gui::GuiPlugin;
+use item::ItemPlugin;
+use level::LevelPlugin;
 use map::{Map, MapPlugin};
+use map_builder::MapBuilderPlugin;
+use minimap::MinimapPlugin;
 use monster::MonsterPlugin;
+use rng::RngPlugin;
+use targeting::TargetingPlugin;
 
 const HUD_ROWS: f32 = 4.0;
 
@@ -44,6 +65,19 @@ enum GameState {
     Menu,
 }
 
+/// What is going on while `GameState::Playing`, turns only go on while `Running`
+#[derive(States, Default, Clone, Copy, Eq, PartialEq, Debug, Hash)]
+enum PlayState {
+    #[default]
+    Running,
+    // The player picks a tile for a ranged item
+    Targeting,
+    // The backpack is open
+    Inventory,
+    // What the player wears is shown
+    Equipment,
+}
+
 #[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
 enum ProcessSet {}
 
@@ -55,6 +89,7 @@ pub struct GamePlugin;
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
         app.init_state::<GameState>()
+            .init_state::<PlayState>()
             .add_plugins((
                 LoadingPlugin,
                 MenuPlugin,
@@ -64,8 +99,16 @@ impl Plugin for GamePlugin {
                 PlayerPlugin,
                 MonsterPlugin,
                 MapPlugin,
+                MapBuilderPlugin,
                 CombatPlugin,
+                RngPlugin,
+                LevelPlugin,
+                DoorPlugin,
+                DijkstraPlugin,
+                CameraPlugin,
+                MinimapPlugin,
             ))
+            .add_plugins((ItemPlugin, TargetingPlugin, EquipmentPlugin))
             .add_systems(Startup, setup_camera)
             .add_systems(
                 Update,
@@ -79,6 +122,14 @@ impl Plugin for GamePlugin {
     }
 }
 
+/// Name to show in the log, for entities that have none it is "Someone"
+fn name_of<'a>(q_names: &'a Query<&Name>, entity: Entity) -> &'a str {
+    q_names
+        .get(entity)
+        .map(|name| name.as_str())
+        .unwrap_or("Someone")
+}
+
 fn setup_camera(mut commands: Commands, map: Res<Map>) {
     let mut camera_bundle = Camera2dBundle::default();
     camera_bundle.transform.translation.x = (map.cols as f32 / 2.0 - 0.5) * map.tile_size as f32;
@@ -37,8 +37,6 @@ pub struct TextureAssets {
     pub map_atlas_layout: Handle<TextureAtlasLayout>,
     #[asset(path = "textures/colored_packed.png")]
     pub map_atlas: Handle<Image>,
-    #[asset(path = "textures/colored_packed_darkened.png")]
-    pub map_atlas_darkened: Handle<Image>,
     #[asset(path = "textures/heart_red.png")]
     pub heart: Handle<Image>,
 }
@@ -1,20 +1,33 @@
 use bevy::prelude::*;
+use bevy::utils::HashSet;
 use bevy_inspector_egui::inspector_options::ReflectInspectorOptions;
 use bevy_inspector_egui::InspectorOptions;
 use bracket_pathfinding::prelude::*;
-use rand::rngs::ThreadRng;
-use rand::Rng;
 
+use crate::level::LevelStore;
 use crate::loading::TextureAssets;
+use crate::map_builder::MapBuilders;
 use crate::monster::Monster;
 use crate::player::{player_input, Player};
+use crate::rng::GameRng;
+use crate::tilemap::{
+    spawn_tile_chunks, FogOfWar, MapChunks, TileChunk, TileFog, TilemapMaterial, TilemapPlugin,
+};
 use crate::GameState;
 
+/// Size of the map in tiles when nothing else is asked for
+const DEFAULT_MAP_SIZE: (usize, usize) = (80, 45);
+
 pub struct MapPlugin;
 
+/// The map can be bigger than the window, the camera follows the player around it
+/// Its size is picked with `--map-size <cols>x<rows>` / `ROGUELIKE_MAP_SIZE=<cols>x<rows>`
 impl Plugin for MapPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(Map::new(80, 45, 16))
+        let (cols, rows) = map_size_from_env().unwrap_or(DEFAULT_MAP_SIZE);
+        app.add_plugins(TilemapPlugin)
+            .insert_resource(Map::new(cols, rows, 16))
+            .insert_resource(Depth(1))
             .register_type::<MapEntity>()
             .register_type::<Map>()
             .register_type::<Rect>()
@@ -28,14 +41,49 @@ impl Plugin for MapPlugin {
                     .after(player_input),
             )
             .add_systems(Update, update_map.run_if(in_state(GameState::Playing)))
-            .add_systems(Update, map_index.run_if(in_state(GameState::Playing)));
+            .add_systems(Update, map_index.run_if(in_state(GameState::Playing)))
+            .add_systems(Update, reveal_on_sight.run_if(in_state(GameState::Playing)));
+    }
+}
+
+fn map_size_from_env() -> Option<(usize, usize)> {
+    let mut args = std::env::args();
+    while let Some(arg) = args.next() {
+        if arg == "--map-size" {
+            return args.next().as_deref().and_then(parse_map_size);
+        }
     }
+
+    std::env::var("ROGUELIKE_MAP_SIZE")
+        .ok()
+        .as_deref()
+        .and_then(parse_map_size)
+}
+
+/// Reads `<cols>x<rows>`, the map needs some room for the builders to work with
+fn parse_map_size(size: &str) -> Option<(usize, usize)> {
+    let (cols, rows) = size.split_once('x')?;
+    let (cols, rows) = (cols.trim().parse().ok()?, rows.trim().parse().ok()?);
+    if cols < 20 || rows < 20 {
+        warn!("Map size {}x{} is too small, using the default", cols, rows);
+        return None;
+    }
+    Some((cols, rows))
 }
 
 #[derive(Reflect, Debug, PartialEq, Clone, Copy)]
 pub enum Tile {
     Floor,
     Wall,
+    DoorClosed,
+    DoorOpen,
+    DoorLocked,
+    ShallowWater,
+    Lava,
+    Bridge,
+    Trap,
+    DownStairs,
+    UpStairs,
 }
 
 impl Tile {
@@ -43,24 +91,89 @@ impl Tile {
         match self {
             Tile::Floor => 2,
             Tile::Wall => 17 * 48 + 10,
+            Tile::DoorClosed => 3 * 48 + 3,
+            Tile::DoorOpen => 3 * 48 + 4,
+            Tile::DoorLocked => 9 * 48 + 4,
+            Tile::ShallowWater => 5 * 48 + 8,
+            Tile::Lava => 10 * 48 + 15,
+            Tile::Bridge => 5 * 48 + 6,
+            Tile::Trap => 22,
+            Tile::DownStairs => 6 * 48 + 2,
+            Tile::UpStairs => 21,
+        }
+    }
+
+    /// Blocks the field of view
+    pub fn is_opaque(&self) -> bool {
+        matches!(self, Tile::Wall | Tile::DoorClosed | Tile::DoorLocked)
+    }
+
+    pub fn is_walkable(&self) -> bool {
+        !matches!(self, Tile::Wall | Tile::DoorClosed | Tile::DoorLocked)
+    }
+
+    /// Not walkable, but anyone can open it on the way
+    pub fn can_open(&self) -> bool {
+        matches!(self, Tile::DoorClosed)
+    }
+
+    /// Multiplier applied to the cost of stepping onto the tile when pathing
+    pub fn movement_cost(&self) -> f32 {
+        match self {
+            // Opening a door takes a turn
+            Tile::DoorClosed => 2.0,
+            Tile::ShallowWater => 2.0,
+            // Walkable, but nobody should pick it unless there is no other way
+            Tile::Lava => 10.0,
+            _ => 1.0,
+        }
+    }
+
+    /// Hp lost by whoever steps onto the tile
+    pub fn damage_on_enter(&self) -> i32 {
+        match self {
+            Tile::Lava => 10,
+            Tile::Trap => 3,
+            _ => 0,
         }
     }
 }
 
+/// Wall sprite for every combination of neighbouring walls, indexed by `Map::wall_mask`
+/// Walls joining three or four others keep the plain brick sprite
+const WALL_SPRITES: [usize; 16] = [
+    20 * 48 + 17, // alone
+    18 * 48 + 9,  // N
+    16 * 48 + 9,  // S
+    17 * 48 + 9,  // N S
+    16 * 48 + 8,  // W
+    21 * 48 + 18, // N W
+    19 * 48 + 18, // S W
+    17 * 48 + 10, // N S W
+    16 * 48 + 7,  // E
+    21 * 48 + 16, // N E
+    19 * 48 + 16, // S E
+    17 * 48 + 10, // N S E
+    16 * 48 + 8,  // W E
+    17 * 48 + 10, // N W E
+    17 * 48 + 10, // S W E
+    17 * 48 + 10, // N S W E
+];
+
 #[derive(Resource, Reflect, Deref)]
 pub struct MapEntity(Entity);
 
-#[derive(Component)]
-pub struct MapTile {
-    pub col: usize,
-    pub row: usize,
-    pub visible: bool,
-    pub revealed: bool,
-}
+/// Current dungeon level, starting from 1
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct Depth(pub usize);
 
 #[derive(Component)]
 pub struct BlockTile;
 
+/// Hidden until the tile it lies on has been seen, like keys and items on the floor
+#[derive(Component)]
+pub struct RevealOnSight;
+
 #[derive(Component, Clone, Copy, Debug, Reflect)]
 pub struct Position {
     pub x: usize,
@@ -131,52 +244,33 @@ pub struct Map {
     pub tile_size: usize,
     pub tileset_grids: (usize, usize),
     pub rooms: Vec<Rect>,
+    pub player_start: (usize, usize),
+    pub spawn_regions: Vec<Vec<usize>>,
+    pub spawn_list: Vec<(usize, String)>,
     pub revealed_tiles: Vec<bool>,
     pub visible_tiles: Vec<bool>,
     pub blocked: Vec<bool>,
     pub tile_content: Vec<Vec<Entity>>,
 
     tiles: Vec<Tile>,
+    /// Bumped every time a tile changes
+    revision: u64,
+    wall_masks: Vec<u8>,
+    /// Tiles set since the wall masks were last updated
+    #[reflect(ignore)]
+    changed_tiles: HashSet<usize>,
+    /// Tiles that look different since they were last drawn
+    #[reflect(ignore)]
+    dirty_tiles: HashSet<usize>,
 }
 
 impl BaseMap for Map {
     fn is_opaque(&self, idx: usize) -> bool {
-        self.tiles[idx] == Tile::Wall
+        self.tiles[idx].is_opaque()
     }
 
     fn get_available_exits(&self, idx: usize) -> SmallVec<[(usize, f32); 10]> {
-        let mut exits = SmallVec::new();
-        let x = idx % self.cols;
-        let y = idx / self.cols;
-        let c = self.cols;
-
-        if self.is_exit_valid(x - 1, y) {
-            exits.push((idx - 1, 1.0));
-        }
-        if self.is_exit_valid(x + 1, y) {
-            exits.push((idx + 1, 1.0));
-        }
-        if self.is_exit_valid(x, y - 1) {
-            exits.push((idx - c, 1.0));
-        }
-        if self.is_exit_valid(x, y + 1) {
-            exits.push((idx + c, 1.0));
-        }
-
-        if self.is_exit_valid(x - 1, y - 1) {
-            exits.push((idx - c - 1, 1.45));
-        }
-        if self.is_exit_valid(x + 1, y - 1) {
-            exits.push((idx - c + 1, 1.45));
-        }
-        if self.is_exit_valid(x - 1, y + 1) {
-            exits.push((idx + c - 1, 1.45));
-        }
-        if self.is_exit_valid(x + 1, y + 1) {
-            exits.push((idx + c + 1, 1.45));
-        }
-
-        exits
+        self.exits(idx, |i| self.blocked[i])
     }
 
     fn get_pathing_distance(&self, idx1: usize, idx2: usize) -> f32 {
@@ -193,6 +287,32 @@ impl Algorithm2D for Map {
     }
 }
 
+/// The map as seen by pathing that ignores whoever stands on the tiles
+pub struct Terrain<'a>(pub &'a Map);
+
+impl BaseMap for Terrain<'_> {
+    fn is_opaque(&self, idx: usize) -> bool {
+        self.0.is_opaque(idx)
+    }
+
+    fn get_available_exits(&self, idx: usize) -> SmallVec<[(usize, f32); 10]> {
+        self.0.exits(idx, |i| {
+            let tile = self.0.tiles[i];
+            !tile.is_walkable() && !tile.can_open()
+        })
+    }
+
+    fn get_pathing_distance(&self, idx1: usize, idx2: usize) -> f32 {
+        self.0.get_pathing_distance(idx1, idx2)
+    }
+}
+
+impl Algorithm2D for Terrain<'_> {
+    fn dimensions(&self) -> Point {
+        self.0.dimensions()
+    }
+}
+
 impl Map {
     pub fn new(cols: usize, rows: usize, tile_size: usize) -> Self {
         Map {
@@ -201,7 +321,14 @@ impl Map {
             tile_size,
             tileset_grids: (1, 1),
             tiles: vec![Tile::Wall; cols * rows],
+            revision: 0,
+            wall_masks: vec![0; cols * rows],
+            changed_tiles: HashSet::new(),
+            dirty_tiles: HashSet::new(),
             rooms: vec![],
+            player_start: (cols / 2, rows / 2),
+            spawn_regions: vec![],
+            spawn_list: vec![],
             revealed_tiles: vec![false; cols * rows],
             visible_tiles: vec![false; cols * rows],
             blocked: vec![false; cols * rows],
@@ -212,7 +339,14 @@ impl Map {
     pub fn clear_map(&mut self) {
         self.tileset_grids = (1, 1);
         self.tiles.fill(Tile::Wall);
+        self.revision += 1;
+        self.wall_masks.fill(0);
+        self.changed_tiles.clear();
+        self.dirty_tiles.clear();
         self.rooms.clear();
+        self.player_start = (self.cols / 2, self.rows / 2);
+        self.spawn_regions.clear();
+        self.spawn_list.clear();
         self.revealed_tiles.fill(false);
         self.visible_tiles.fill(false);
         self.blocked.fill(false);
@@ -220,13 +354,144 @@ impl Map {
     }
 
     pub fn set_tile(&mut self, col: usize, row: usize, tile: Tile) {
-        self.tiles[row * self.cols + col] = tile;
+        let idx = row * self.cols + col;
+        if self.tiles[idx] != tile {
+            self.tiles[idx] = tile;
+            self.revision += 1;
+            self.changed_tiles.insert(idx);
+            self.dirty_tiles.insert(idx);
+        }
+    }
+
+    /// Replaces the tiles in view of the player, everything in view gets revealed
+    pub fn update_visible_tiles(&mut self, visible: &[Point]) {
+        for idx in 0..self.visible_tiles.len() {
+            if self.visible_tiles[idx] {
+                self.visible_tiles[idx] = false;
+                self.dirty_tiles.insert(idx);
+            }
+        }
+
+        for pos in visible.iter() {
+            let idx = self.xy_to_index(pos.x as usize, pos.y as usize);
+            self.revealed_tiles[idx] = true;
+            self.visible_tiles[idx] = true;
+            self.dirty_tiles.insert(idx);
+        }
+    }
+
+    /// Has every tile drawn again, like after the map got replaced
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty_tiles.extend(0..self.cols * self.rows);
+    }
+
+    /// The tiles to draw again, they count as drawn afterwards
+    pub fn take_dirty_tiles(&mut self) -> Vec<usize> {
+        self.dirty_tiles.drain().collect()
     }
 
     pub fn get_tile(&self, col: usize, row: usize) -> Tile {
         self.tiles[row * self.cols + col]
     }
 
+    pub fn find_tile(&self, tile: Tile) -> Option<(usize, usize)> {
+        self.tiles
+            .iter()
+            .position(|t| *t == tile)
+            .map(|idx| (idx % self.cols, idx / self.cols))
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Sprite of the tile, walls are picked by their neighbours
+    pub fn tile_sprite(&self, col: usize, row: usize) -> usize {
+        let idx = self.xy_to_index(col, row);
+        match self.tiles[idx] {
+            Tile::Wall => WALL_SPRITES[self.wall_masks[idx] as usize],
+            tile => tile.index_in_sprite_sheet(),
+        }
+    }
+
+    /// Recomputes the wall masks of the whole map
+    pub fn compute_wall_masks(&mut self) {
+        self.changed_tiles.clear();
+        for y in 0..self.rows {
+            for x in 0..self.cols {
+                let idx = self.xy_to_index(x, y);
+                self.wall_masks[idx] = self.wall_mask(x, y);
+            }
+        }
+    }
+
+    /// Recomputes the wall masks around the tiles changed since the last update
+    /// Returns false when nothing changed
+    pub fn update_wall_masks(&mut self) -> bool {
+        if self.changed_tiles.is_empty() {
+            return false;
+        }
+
+        // A tile decides whether its neighbours are exposed walls, which in turn decide their neighbours' masks
+        let changed: Vec<usize> = self.changed_tiles.drain().collect();
+        for idx in changed {
+            let (x, y) = (idx % self.cols, idx / self.cols);
+            for ny in y.saturating_sub(2)..=(y + 2).min(self.rows - 1) {
+                for nx in x.saturating_sub(2)..=(x + 2).min(self.cols - 1) {
+                    let nidx = self.xy_to_index(nx, ny);
+                    let mask = self.wall_mask(nx, ny);
+                    if self.wall_masks[nidx] != mask {
+                        self.wall_masks[nidx] = mask;
+                        self.dirty_tiles.insert(nidx);
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Bits set for the exposed walls to the north, south, west and east
+    fn wall_mask(&self, x: usize, y: usize) -> u8 {
+        let (x, y) = (x as i32, y as i32);
+        if !self.is_wall(x, y) {
+            return 0;
+        }
+        // Solid rock is never seen, it simply keeps the brick sprite
+        if !self.is_exposed_wall(x, y) {
+            return 15;
+        }
+
+        let mut mask = 0;
+        if self.is_exposed_wall(x, y + 1) {
+            mask |= 1;
+        }
+        if self.is_exposed_wall(x, y - 1) {
+            mask |= 2;
+        }
+        if self.is_exposed_wall(x - 1, y) {
+            mask |= 4;
+        }
+        if self.is_exposed_wall(x + 1, y) {
+            mask |= 8;
+        }
+        mask
+    }
+
+    fn is_wall(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x >= self.cols as i32 || y >= self.rows as i32 {
+            return true;
+        }
+        self.tiles[self.xy_to_index(x as usize, y as usize)] == Tile::Wall
+    }
+
+    /// A wall with something other than wall around it, solid rock doesn't count
+    fn is_exposed_wall(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x >= self.cols as i32 || y >= self.rows as i32 || !self.is_wall(x, y) {
+            return false;
+        }
+        (-1..=1).any(|dy| (-1..=1).any(|dx| !self.is_wall(x + dx, y + dy)))
+    }
+
     pub fn get_tile_index_in_sprite_sheet(&self, col: usize, row: usize) -> usize {
         row * self.tileset_grids.0 + col
     }
@@ -257,17 +522,59 @@ impl Map {
 
     pub fn populate_blocked(&mut self) {
         for (i, tile) in self.tiles.iter_mut().enumerate() {
-            self.blocked[i] = *tile == Tile::Wall;
+            self.blocked[i] = !tile.is_walkable() && !tile.can_open();
+        }
+    }
+
+    fn exits(
+        &self,
+        idx: usize,
+        is_blocked: impl Fn(usize) -> bool,
+    ) -> SmallVec<[(usize, f32); 10]> {
+        let mut exits = SmallVec::new();
+        let x = idx % self.cols;
+        let y = idx / self.cols;
+        let c = self.cols;
+
+        if self.is_exit_valid(x - 1, y, &is_blocked) {
+            exits.push((idx - 1, self.exit_cost(idx - 1, 1.0)));
+        }
+        if self.is_exit_valid(x + 1, y, &is_blocked) {
+            exits.push((idx + 1, self.exit_cost(idx + 1, 1.0)));
+        }
+        if self.is_exit_valid(x, y - 1, &is_blocked) {
+            exits.push((idx - c, self.exit_cost(idx - c, 1.0)));
+        }
+        if self.is_exit_valid(x, y + 1, &is_blocked) {
+            exits.push((idx + c, self.exit_cost(idx + c, 1.0)));
+        }
+
+        if self.is_exit_valid(x - 1, y - 1, &is_blocked) {
+            exits.push((idx - c - 1, self.exit_cost(idx - c - 1, 1.45)));
+        }
+        if self.is_exit_valid(x + 1, y - 1, &is_blocked) {
+            exits.push((idx - c + 1, self.exit_cost(idx - c + 1, 1.45)));
+        }
+        if self.is_exit_valid(x - 1, y + 1, &is_blocked) {
+            exits.push((idx + c - 1, self.exit_cost(idx + c - 1, 1.45)));
         }
+        if self.is_exit_valid(x + 1, y + 1, &is_blocked) {
+            exits.push((idx + c + 1, self.exit_cost(idx + c + 1, 1.45)));
+        }
+
+        exits
     }
 
-    fn is_exit_valid(&self, x: usize, y: usize) -> bool {
+    fn is_exit_valid(&self, x: usize, y: usize, is_blocked: &impl Fn(usize) -> bool) -> bool {
         if x < 1 || x > self.cols - 1 || y < 1 || y > self.rows - 1 {
             return false;
         }
 
-        let idx = self.xy_to_index(x, y);
-        !self.blocked[idx]
+        !is_blocked(self.xy_to_index(x, y))
+    }
+
+    fn exit_cost(&self, idx: usize, distance: f32) -> f32 {
+        distance * self.tiles[idx].movement_cost()
     }
 
     fn clear_content_index(&mut self) {
@@ -277,16 +584,31 @@ impl Map {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn spawn_map(
     mut commands: Commands,
     mut map: ResMut<Map>,
+    depth: Res<Depth>,
+    levels: Res<LevelStore>,
+    builders: Res<MapBuilders>,
+    mut rng: ResMut<GameRng>,
     texture_assets: Res<TextureAssets>,
     images: Res<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<TilemapMaterial>>,
+    fog_of_war: Res<FogOfWar>,
 ) {
-    let cols = map.cols;
-    let rows = map.rows;
-
-    new_map_rooms_and_corridors(&mut map);
+    if let Some(level) = levels.get(&depth.0) {
+        // Visited before, bring back the level as the player left it
+        *map = level.map.clone();
+    } else {
+        builders.build(depth.0, &mut map, &mut rng);
+
+        if depth.0 > 1 {
+            let (x, y) = map.player_start;
+            map.set_tile(x, y, Tile::UpStairs);
+        }
+    }
 
     let map_atlas_image = images.get(&texture_assets.map_atlas).unwrap();
     let (atlas_cols, atlas_rows) = (
@@ -296,41 +618,30 @@ pub(crate) fn spawn_map(
 
     map.tileset_grids = (atlas_cols, atlas_rows);
     info!("Tileset grids: {:?}", map.tileset_grids);
-
-    for r in 0..rows {
-        for c in 0..cols {
-            commands.spawn((
-                SpriteSheetBundle {
-                    transform: Transform {
-                        translation: Vec3::new(
-                            (c * map.tile_size) as f32,
-                            (r * map.tile_size) as f32,
-                            0.,
-                        ),
-                        // scale: Vec3::splat(1.0),
-                        ..default()
-                    },
-                    texture: texture_assets.map_atlas.clone(),
-                    atlas: TextureAtlas {
-                        index: map.get_tile(c, r).index_in_sprite_sheet(),
-                        layout: texture_assets.map_atlas_layout.clone(),
-                    },
-                    ..default()
-                },
-                MapTile {
-                    col: c,
-                    row: r,
-                    visible: false,
-                    revealed: false,
-                },
-            ));
-        }
-    }
+    map.compute_wall_masks();
+
+    let map_chunks = spawn_tile_chunks(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        &map,
+        &texture_assets,
+        &fog_of_war,
+    );
+    commands.insert_resource(map_chunks);
 }
 
-pub(crate) fn clear_map(mut commands: Commands, mut map: ResMut<Map>) {
+pub(crate) fn clear_map(
+    mut commands: Commands,
+    mut map: ResMut<Map>,
+    mut map_chunks: ResMut<MapChunks>,
+    q_chunks: Query<Entity, With<TileChunk>>,
+) {
     map.clear_map();
-    // commands.run_system(update_map);
+    *map_chunks = MapChunks::default();
+    q_chunks.iter().for_each(|chunk| {
+        commands.entity(chunk).despawn_recursive();
+    });
 }
 
 fn update_view(
@@ -354,16 +665,7 @@ fn update_view(
                 .visible_tiles
                 .retain(|p| p.x >= 0 && p.x < map.cols as i32 && p.y >= 0 && p.y < map.rows as i32);
 
-            for t in map.visible_tiles.iter_mut() {
-                *t = false;
-            }
-
-            for pos in viewshed.visible_tiles.iter() {
-                let idx = map.xy_to_index(pos.x as usize, pos.y as usize);
-
-                map.revealed_tiles[idx] = true;
-                map.visible_tiles[idx] = true;
-            }
+            map.update_visible_tiles(&viewshed.visible_tiles);
         }
 
         monster_view
@@ -389,42 +691,27 @@ fn update_view(
     }
 }
 
-pub(crate) fn update_map(
-    mut q_tile: Query<(
-        &mut Handle<Image>,
-        &mut TextureAtlas,
-        &MapTile,
-        &mut Visibility,
-    )>,
-    map: Res<Map>,
-    texture_assets: Res<TextureAssets>,
+/// Only redraws the tiles that changed since the last frame
+pub fn update_map(
+    mut map: ResMut<Map>,
+    map_chunks: Res<MapChunks>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    fog_of_war: Res<FogOfWar>,
+    q_player: Query<(&Position, &Viewshed), With<Player>>,
 ) {
-    if !map.is_changed() {
-        return;
+    // Drawing doesn't change anything the other systems care about
+    let map = map.bypass_change_detection();
+    map.update_wall_masks();
+
+    let dirty_tiles = map.take_dirty_tiles();
+    if !dirty_tiles.is_empty() {
+        let viewer = q_player
+            .get_single()
+            .ok()
+            .map(|(pos, viewshed)| (Point::from(pos), viewshed.range));
+        let fog = TileFog::new(&fog_of_war, viewer);
+        map_chunks.redraw_tiles(map, &mut meshes, &fog, dirty_tiles);
     }
-    q_tile.iter_mut().for_each(
-        |(mut spritesheet, mut tile_atlas, tile, mut tile_visible)| {
-            tile_atlas.index = map.get_tile(tile.col, tile.row).index_in_sprite_sheet();
-
-            if map.revealed_tiles[map.xy_to_index(tile.col, tile.row)] {
-                if map.visible_tiles[map.xy_to_index(tile.col, tile.row)] {
-                    if *spritesheet != texture_assets.map_atlas {
-                        *spritesheet = texture_assets.map_atlas.clone();
-                    }
-                } else {
-                    if *spritesheet != texture_assets.map_atlas_darkened {
-                        *spritesheet = texture_assets.map_atlas_darkened.clone();
-                    }
-                };
-
-                if *tile_visible == Visibility::Hidden {
-                    *tile_visible = Visibility::Visible;
-                }
-            } else {
-                *tile_visible = Visibility::Hidden;
-            }
-        },
-    );
 }
 
 pub fn map_index(
@@ -432,6 +719,8 @@ pub fn map_index(
     q_blocks: Query<&Position, With<BlockTile>>,
     q_position: Query<(Entity, &Position), With<Monster>>,
 ) {
+    // The index is rebuilt every frame, it shouldn't count as a change of the map
+    let map = map.bypass_change_detection();
     map.populate_blocked();
     map.clear_content_index();
 
@@ -445,6 +734,22 @@ pub fn map_index(
     });
 }
 
+/// Shows what lies on the tiles once they have been seen
+fn reveal_on_sight(
+    map: Res<Map>,
+    mut q_hidden: Query<(&Position, &mut Visibility), With<RevealOnSight>>,
+) {
+    if !map.is_changed() {
+        return;
+    }
+
+    q_hidden.iter_mut().for_each(|(pos, mut visibility)| {
+        if map.revealed_tiles[map.xy_to_index(pos.x, pos.y)] && *visibility == Visibility::Hidden {
+            *visibility = Visibility::Visible;
+        }
+    });
+}
+
 fn update_blocks(q_blocks: Query<&Position, With<BlockTile>>, mut map: ResMut<Map>) {
     map.populate_blocked();
 
@@ -454,55 +759,3 @@ fn update_blocks(q_blocks: Query<&Position, With<BlockTile>>, mut map: ResMut<Ma
         map.blocked[idx] = true;
     });
 }
-
-fn new_map_rooms_and_corridors(map: &mut Map) {
-    let cols = map.cols;
-    let rows = map.rows;
-
-    map.set_horizontal_line(0, cols - 1, 0, Tile::Wall);
-    map.set_horizontal_line(0, cols - 1, rows - 1, Tile::Wall);
-    map.set_vertical_line(0, 0, rows - 1, Tile::Wall);
-    map.set_vertical_line(cols - 1, 0, rows - 1, Tile::Wall);
-
-    let mut rooms: Vec<Rect> = Vec::new();
-    const MAX_ROOMS: usize = 20;
-    const MIN_SIZE: usize = 6;
-    const MAX_SIZE: usize = 10;
-
-    let mut rng = ThreadRng::default();
-
-    for _ in 0..MAX_ROOMS {
-        let w = rng.gen_range(MIN_SIZE..MAX_SIZE);
-        let h = rng.gen_range(MIN_SIZE..MAX_SIZE);
-        let x = rng.gen_range(1..(cols - w - 1));
-        let y = rng.gen_range(1..(rows - h - 1));
-
-        let new_room = Rect::new(x, y, w, h);
-        let mut ok = true;
-        for other_room in &rooms {
-            if new_room.intersect(other_room) {
-                ok = false;
-            }
-        }
-
-        if ok {
-            map.set_rect(&new_room, Tile::Floor);
-
-            if !rooms.is_empty() {
-                let (new_x, new_y) = new_room.center();
-                let (prev_x, prev_y) = rooms[rooms.len() - 1].center();
-                if rng.gen_range(0..2) == 1 {
-                    map.set_horizontal_line(prev_x, new_x, prev_y, Tile::Floor);
-                    map.set_vertical_line(new_x, prev_y, new_y, Tile::Floor);
-                } else {
-                    map.set_vertical_line(prev_x, prev_y, new_y, Tile::Floor);
-                    map.set_horizontal_line(prev_x, new_x, new_y, Tile::Floor);
-                }
-            }
-
-            rooms.push(new_room);
-        }
-    }
-
-    map.rooms = rooms;
-}
@@ -0,0 +1,123 @@
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use super::{rooms_to_spawn_regions, MapBuilder};
+use crate::map::{Map, Rect, Tile};
+
+/// Binary space partition dungeon
+/// The map is split recursively into leaves, every leaf gets one room
+/// and the rooms of sibling subtrees are connected by a corridor
+pub struct BspBuilder {
+    pub max_leaf_size: usize,
+    pub min_leaf_size: usize,
+    pub min_room_size: usize,
+    rooms: Vec<Rect>,
+    spawn_regions: Vec<Vec<usize>>,
+}
+
+impl Default for BspBuilder {
+    fn default() -> Self {
+        BspBuilder {
+            max_leaf_size: 16,
+            min_leaf_size: 8,
+            min_room_size: 3,
+            rooms: Vec::new(),
+            spawn_regions: Vec::new(),
+        }
+    }
+}
+
+impl MapBuilder for BspBuilder {
+    fn build_map(&mut self, map: &mut Map, rng: &mut StdRng) {
+        let root = Rect {
+            x1: 1,
+            y1: 1,
+            x2: map.cols - 2,
+            y2: map.rows - 2,
+        };
+        self.partition(map, rng, root);
+
+        self.spawn_regions = rooms_to_spawn_regions(map, &self.rooms);
+    }
+
+    fn get_rooms(&self) -> Vec<Rect> {
+        self.rooms.clone()
+    }
+
+    fn get_starting_position(&self) -> (usize, usize) {
+        self.rooms[0].center()
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        self.spawn_regions.clone()
+    }
+}
+
+impl BspBuilder {
+    /// Splits the leaf along its longer side until it is small enough,
+    /// returns the index of a room inside the leaf to connect it with its sibling
+    fn partition(&mut self, map: &mut Map, rng: &mut StdRng, leaf: Rect) -> usize {
+        let w = leaf.x2 - leaf.x1;
+        let h = leaf.y2 - leaf.y1;
+        let can_split_x = w > self.min_leaf_size * 2;
+        let can_split_y = h > self.min_leaf_size * 2;
+
+        if (w <= self.max_leaf_size && h <= self.max_leaf_size) || !(can_split_x || can_split_y) {
+            return self.carve_room(map, rng, leaf);
+        }
+
+        let split_x = if can_split_x && can_split_y {
+            w >= h
+        } else {
+            can_split_x
+        };
+
+        let (first, second) = if split_x {
+            let at = rng.gen_range((leaf.x1 + self.min_leaf_size)..(leaf.x2 - self.min_leaf_size));
+            (Rect { x2: at, ..leaf }, Rect { x1: at + 1, ..leaf })
+        } else {
+            let at = rng.gen_range((leaf.y1 + self.min_leaf_size)..(leaf.y2 - self.min_leaf_size));
+            (Rect { y2: at, ..leaf }, Rect { y1: at + 1, ..leaf })
+        };
+
+        let first_room = self.partition(map, rng, first);
+        let second_room = self.partition(map, rng, second);
+        self.connect(map, rng, first_room, second_room);
+
+        // Pick either side so corridors don't all converge on the same room
+        if rng.gen_bool(0.5) {
+            first_room
+        } else {
+            second_room
+        }
+    }
+
+    fn carve_room(&mut self, map: &mut Map, rng: &mut StdRng, leaf: Rect) -> usize {
+        let w = leaf.x2 - leaf.x1;
+        let h = leaf.y2 - leaf.y1;
+
+        let room_w = rng.gen_range(self.min_room_size.min(w - 2)..=(w - 2));
+        let room_h = rng.gen_range(self.min_room_size.min(h - 2)..=(h - 2));
+        let x = rng.gen_range((leaf.x1 + 1)..=(leaf.x2 - 1 - room_w));
+        let y = rng.gen_range((leaf.y1 + 1)..=(leaf.y2 - 1 - room_h));
+
+        let room = Rect::new(x, y, room_w, room_h);
+        map.set_rect(&room, Tile::Floor);
+        self.rooms.push(room);
+
+        self.rooms.len() - 1
+    }
+
+    fn connect(&self, map: &mut Map, rng: &mut StdRng, a: usize, b: usize) {
+        let (a_x, a_y) = self.rooms[a].center();
+        let (b_x, b_y) = self.rooms[b].center();
+
+        if rng.gen_bool(0.5) {
+            map.set_horizontal_line(a_x, b_x, a_y, Tile::Floor);
+            map.set_vertical_line(b_x, a_y, b_y, Tile::Floor);
+        } else {
+            map.set_vertical_line(a_x, a_y, b_y, Tile::Floor);
+            map.set_horizontal_line(a_x, b_x, b_y, Tile::Floor);
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use super::{cull_unreachable, floor_chunks, nearest_floor, MapBuilder};
+use crate::map::{Map, Rect, Tile};
+
+/// Cave levels: random noise smoothed by a cellular automaton
+pub struct CellularAutomataBuilder {
+    /// Chance for a tile of the initial noise to be a floor
+    pub floor_chance: f64,
+    pub iterations: usize,
+    starting_position: (usize, usize),
+    spawn_regions: Vec<Vec<usize>>,
+}
+
+impl Default for CellularAutomataBuilder {
+    fn default() -> Self {
+        CellularAutomataBuilder {
+            floor_chance: 0.55,
+            iterations: 15,
+            starting_position: (0, 0),
+            spawn_regions: Vec::new(),
+        }
+    }
+}
+
+impl MapBuilder for CellularAutomataBuilder {
+    fn build_map(&mut self, map: &mut Map, rng: &mut StdRng) {
+        for y in 1..map.rows - 1 {
+            for x in 1..map.cols - 1 {
+                let tile = if rng.gen_bool(self.floor_chance) {
+                    Tile::Floor
+                } else {
+                    Tile::Wall
+                };
+                map.set_tile(x, y, tile);
+            }
+        }
+
+        for _ in 0..self.iterations {
+            let mut next = map.clone();
+            for y in 1..map.rows - 1 {
+                for x in 1..map.cols - 1 {
+                    let walls = count_wall_neighbours(map, x, y);
+                    // Walls grow where they are dense and erode where they are sparse
+                    if walls > 4 {
+                        next.set_tile(x, y, Tile::Wall);
+                    } else if walls < 4 {
+                        next.set_tile(x, y, Tile::Floor);
+                    }
+                }
+            }
+            *map = next;
+        }
+
+        self.starting_position = nearest_floor(map, map.cols / 2, map.rows / 2);
+        cull_unreachable(map, self.starting_position);
+
+        self.spawn_regions = floor_chunks(map, self.starting_position);
+    }
+
+    fn get_rooms(&self) -> Vec<Rect> {
+        Vec::new()
+    }
+
+    fn get_starting_position(&self) -> (usize, usize) {
+        self.starting_position
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        self.spawn_regions.clone()
+    }
+}
+
+fn count_wall_neighbours(map: &Map, x: usize, y: usize) -> usize {
+    let mut walls = 0;
+    for ny in y - 1..=y + 1 {
+        for nx in x - 1..=x + 1 {
+            if (nx, ny) != (x, y) && map.get_tile(nx, ny) == Tile::Wall {
+                walls += 1;
+            }
+        }
+    }
+    walls
+}
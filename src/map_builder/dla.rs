@@ -0,0 +1,142 @@
+use bracket_pathfinding::prelude::{line2d, LineAlg, Point};
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use super::{cull_unreachable, floor_chunks, random_step, MapBuilder};
+use crate::map::{Map, Rect, Tile};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DlaAlgorithm {
+    /// Diggers wander in from random points around the floor and stick to the first floor they touch
+    WalkInwards,
+    /// Diggers wander out from the center and stop at the first wall
+    WalkOutwards,
+    /// Diggers travel in a straight line from random points towards the center
+    CentralAttractor,
+}
+
+/// How far from the floor dug so far the inwards walkers start
+const SPAWN_MARGIN: usize = 4;
+
+/// Diffusion-limited aggregation
+pub struct DlaBuilder {
+    pub algorithm: DlaAlgorithm,
+    /// Fraction of the map that should be floor
+    pub floor_percent: f32,
+    starting_position: (usize, usize),
+    spawn_regions: Vec<Vec<usize>>,
+}
+
+impl Default for DlaBuilder {
+    fn default() -> Self {
+        DlaBuilder::new(DlaAlgorithm::WalkInwards)
+    }
+}
+
+impl DlaBuilder {
+    pub fn new(algorithm: DlaAlgorithm) -> Self {
+        DlaBuilder {
+            algorithm,
+            floor_percent: 0.25,
+            starting_position: (0, 0),
+            spawn_regions: Vec::new(),
+        }
+    }
+}
+
+impl MapBuilder for DlaBuilder {
+    fn build_map(&mut self, map: &mut Map, rng: &mut StdRng) {
+        let start = (map.cols / 2, map.rows / 2);
+        // Seed of the aggregate
+        map.set_tile(start.0, start.1, Tile::Floor);
+        map.set_tile(start.0 - 1, start.1, Tile::Floor);
+        map.set_tile(start.0 + 1, start.1, Tile::Floor);
+        map.set_tile(start.0, start.1 - 1, Tile::Floor);
+        map.set_tile(start.0, start.1 + 1, Tile::Floor);
+        let mut floor_tiles = vec![
+            start,
+            (start.0 - 1, start.1),
+            (start.0 + 1, start.1),
+            (start.0, start.1 - 1),
+            (start.0, start.1 + 1),
+        ];
+
+        let desired_floor = ((map.cols * map.rows) as f32 * self.floor_percent) as usize;
+        // Bail out if the target is unreachable for this map size
+        // Walking out of the middle of a big cave takes a while, so the limit is higher than the drunkard's
+        let max_steps = map.cols * map.rows * 500;
+        let mut steps = 0;
+
+        while floor_tiles.len() < desired_floor && steps < max_steps {
+            let (x, y) = match self.algorithm {
+                DlaAlgorithm::WalkInwards => {
+                    // Walkers from the far corners of big maps would take ages to get there
+                    let (fx, fy) = floor_tiles[rng.gen_range(0..floor_tiles.len())];
+                    let mut x = rng.gen_range(
+                        fx.saturating_sub(SPAWN_MARGIN).max(1)
+                            ..=(fx + SPAWN_MARGIN).min(map.cols - 2),
+                    );
+                    let mut y = rng.gen_range(
+                        fy.saturating_sub(SPAWN_MARGIN).max(1)
+                            ..=(fy + SPAWN_MARGIN).min(map.rows - 2),
+                    );
+                    let (mut prev_x, mut prev_y) = (x, y);
+                    while map.get_tile(x, y) == Tile::Wall && steps < max_steps {
+                        (prev_x, prev_y) = (x, y);
+                        random_step(map, rng, &mut x, &mut y);
+                        steps += 1;
+                    }
+                    (prev_x, prev_y)
+                }
+                DlaAlgorithm::WalkOutwards => {
+                    let (mut x, mut y) = start;
+                    while map.get_tile(x, y) == Tile::Floor && steps < max_steps {
+                        random_step(map, rng, &mut x, &mut y);
+                        steps += 1;
+                    }
+                    (x, y)
+                }
+                DlaAlgorithm::CentralAttractor => {
+                    let from = Point::new(
+                        rng.gen_range(1..map.cols - 1),
+                        rng.gen_range(1..map.rows - 1),
+                    );
+                    let path = line2d(LineAlg::Bresenham, from, Point::new(start.0, start.1));
+                    let (mut prev_x, mut prev_y) = (from.x as usize, from.y as usize);
+                    for step in path {
+                        steps += 1;
+                        let (x, y) = (step.x as usize, step.y as usize);
+                        if map.get_tile(x, y) == Tile::Floor {
+                            break;
+                        }
+                        (prev_x, prev_y) = (x, y);
+                    }
+                    (prev_x, prev_y)
+                }
+            };
+
+            // A digger that ran out of steps may leave a stray tile, culling removes it
+            if map.get_tile(x, y) == Tile::Wall {
+                map.set_tile(x, y, Tile::Floor);
+                floor_tiles.push((x, y));
+            }
+        }
+
+        self.starting_position = start;
+        cull_unreachable(map, start);
+
+        self.spawn_regions = floor_chunks(map, start);
+    }
+
+    fn get_rooms(&self) -> Vec<Rect> {
+        Vec::new()
+    }
+
+    fn get_starting_position(&self) -> (usize, usize) {
+        self.starting_position
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        self.spawn_regions.clone()
+    }
+}
@@ -0,0 +1,89 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use super::distances_from;
+use crate::door::KEY_NAME;
+use crate::map::{Map, Tile};
+
+/// Chance for every room entrance to get a door
+const DOOR_CHANCE: f64 = 0.6;
+const MAX_LOCKED_DOORS: usize = 2;
+
+/// Puts closed doors where corridors enter the rooms
+pub(crate) fn place_doors(map: &mut Map, rng: &mut StdRng) {
+    let rooms = map.rooms.clone();
+    for room in rooms.iter() {
+        let (x1, y1) = (room.x1.saturating_sub(1), room.y1.saturating_sub(1));
+        let (x2, y2) = (
+            (room.x2 + 1).min(map.cols - 1),
+            (room.y2 + 1).min(map.rows - 1),
+        );
+
+        for y in y1..=y2 {
+            for x in x1..=x2 {
+                let on_border = x == x1 || x == x2 || y == y1 || y == y2;
+                if on_border && is_doorway(map, x, y) && rng.gen_bool(DOOR_CHANCE) {
+                    map.set_tile(x, y, Tile::DoorClosed);
+                }
+            }
+        }
+    }
+}
+
+/// A floor tile squeezed between two walls, with no other door next to it
+fn is_doorway(map: &Map, x: usize, y: usize) -> bool {
+    if x < 1 || y < 1 || x + 1 >= map.cols || y + 1 >= map.rows {
+        return false;
+    }
+    if map.get_tile(x, y) != Tile::Floor || (x, y) == map.player_start {
+        return false;
+    }
+
+    let (left, right) = (map.get_tile(x - 1, y), map.get_tile(x + 1, y));
+    let (down, up) = (map.get_tile(x, y - 1), map.get_tile(x, y + 1));
+    if [left, right, down, up].contains(&Tile::DoorClosed) {
+        return false;
+    }
+
+    let horizontal =
+        left == Tile::Wall && right == Tile::Wall && down.is_walkable() && up.is_walkable();
+    let vertical =
+        down == Tile::Wall && up == Tile::Wall && left.is_walkable() && right.is_walkable();
+    horizontal || vertical
+}
+
+/// Locks some of the doors and drops a key for each of them
+/// somewhere the player can get to without going through a locked door
+pub(crate) fn lock_doors(map: &mut Map, rng: &mut StdRng) {
+    let doors: Vec<usize> = (0..map.cols * map.rows)
+        .filter(|idx| map.get_tile(idx % map.cols, idx / map.cols) == Tile::DoorClosed)
+        .collect();
+    let count = rng.gen_range(0..=MAX_LOCKED_DOORS).min(doors.len());
+    let locked: Vec<usize> = doors.choose_multiple(rng, count).copied().collect();
+    for idx in locked.iter() {
+        map.set_tile(idx % map.cols, idx / map.cols, Tile::DoorLocked);
+    }
+
+    map.populate_blocked();
+    let start_idx = map.xy_to_index(map.player_start.0, map.player_start.1);
+    let dijkstra = distances_from(map, start_idx);
+    let mut key_spots: Vec<usize> = (0..map.cols * map.rows)
+        .filter(|idx| {
+            *idx != start_idx
+                && dijkstra.map[*idx] < f32::MAX
+                && map.get_tile(idx % map.cols, idx / map.cols) == Tile::Floor
+                && !map.spawn_list.iter().any(|(spawn, _)| spawn == idx)
+        })
+        .collect();
+    key_spots.shuffle(rng);
+
+    for idx in locked {
+        match key_spots.pop() {
+            Some(spot) => map.spawn_list.push((spot, KEY_NAME.to_string())),
+            // Nowhere to leave the key, so the door stays unlocked
+            None => map.set_tile(idx % map.cols, idx / map.cols, Tile::DoorClosed),
+        }
+    }
+    map.populate_blocked();
+}
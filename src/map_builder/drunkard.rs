@@ -0,0 +1,109 @@
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use super::{cull_unreachable, floor_chunks, random_step, MapBuilder};
+use crate::map::{Map, Rect, Tile};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DrunkSpawnMode {
+    /// Every walker starts from the center of the map
+    StartingPoint,
+    /// Walkers start from a random floor tile dug so far
+    Random,
+}
+
+/// Drunkard's walk: walkers dig random paths until enough of the map is floor
+pub struct DrunkardsWalkBuilder {
+    pub spawn_mode: DrunkSpawnMode,
+    /// Steps a walker takes before it stops, `None` walks until the floor target is reached
+    pub lifetime: Option<usize>,
+    /// Fraction of the map that should be floor
+    pub floor_percent: f32,
+    starting_position: (usize, usize),
+    spawn_regions: Vec<Vec<usize>>,
+}
+
+impl Default for DrunkardsWalkBuilder {
+    fn default() -> Self {
+        DrunkardsWalkBuilder::open_area()
+    }
+}
+
+impl DrunkardsWalkBuilder {
+    pub fn new(spawn_mode: DrunkSpawnMode, lifetime: Option<usize>, floor_percent: f32) -> Self {
+        DrunkardsWalkBuilder {
+            spawn_mode,
+            lifetime,
+            floor_percent,
+            starting_position: (0, 0),
+            spawn_regions: Vec::new(),
+        }
+    }
+
+    /// A single walker roaming from the center, makes a large open cave
+    pub fn open_area() -> Self {
+        DrunkardsWalkBuilder::new(DrunkSpawnMode::StartingPoint, Some(400), 0.5)
+    }
+
+    /// Many walkers starting from everywhere, makes wide halls
+    pub fn open_halls() -> Self {
+        DrunkardsWalkBuilder::new(DrunkSpawnMode::Random, Some(400), 0.5)
+    }
+
+    /// Short lived walkers, makes narrow twisting passages
+    pub fn winding_passages() -> Self {
+        DrunkardsWalkBuilder::new(DrunkSpawnMode::Random, Some(100), 0.4)
+    }
+}
+
+impl MapBuilder for DrunkardsWalkBuilder {
+    fn build_map(&mut self, map: &mut Map, rng: &mut StdRng) {
+        let start = (map.cols / 2, map.rows / 2);
+        map.set_tile(start.0, start.1, Tile::Floor);
+        let mut floor_tiles = vec![start];
+
+        let desired_floor = ((map.cols * map.rows) as f32 * self.floor_percent) as usize;
+        // Bail out if the target is unreachable for this map size
+        let max_steps = map.cols * map.rows * 100;
+        let mut steps = 0;
+
+        // Every floor tile is dug by a walker, so they are all in `floor_tiles`
+        while floor_tiles.len() < desired_floor && steps < max_steps {
+            let (mut x, mut y) = match self.spawn_mode {
+                DrunkSpawnMode::StartingPoint => start,
+                DrunkSpawnMode::Random => floor_tiles[rng.gen_range(0..floor_tiles.len())],
+            };
+
+            let mut life = self.lifetime.unwrap_or(usize::MAX);
+            while life > 0 && steps < max_steps {
+                if map.get_tile(x, y) == Tile::Wall {
+                    map.set_tile(x, y, Tile::Floor);
+                    floor_tiles.push((x, y));
+                    if self.lifetime.is_none() && floor_tiles.len() >= desired_floor {
+                        break;
+                    }
+                }
+                random_step(map, rng, &mut x, &mut y);
+                life -= 1;
+                steps += 1;
+            }
+        }
+
+        self.starting_position = start;
+        cull_unreachable(map, start);
+
+        self.spawn_regions = floor_chunks(map, start);
+    }
+
+    fn get_rooms(&self) -> Vec<Rect> {
+        Vec::new()
+    }
+
+    fn get_starting_position(&self) -> (usize, usize) {
+        self.starting_position
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        self.spawn_regions.clone()
+    }
+}
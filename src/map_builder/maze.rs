@@ -0,0 +1,157 @@
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use super::{cull_unreachable, floor_chunks, rooms_to_spawn_regions, scale_with_area, MapBuilder};
+use crate::map::{Map, Rect, Tile};
+
+/// Labyrinth carved by a recursive backtracker
+/// Cells sit on odd coordinates, the even ones in between are the walls that get knocked down
+pub struct MazeBuilder {
+    /// Rooms carved on top of the maze of an 80x45 map, bigger maps get more of them
+    pub room_count: usize,
+    pub min_room_size: usize,
+    pub max_room_size: usize,
+    /// Passes of dead end removal, every pass shortens every dead end by one tile
+    pub prune_passes: usize,
+    rooms: Vec<Rect>,
+    starting_position: (usize, usize),
+    spawn_regions: Vec<Vec<usize>>,
+}
+
+impl Default for MazeBuilder {
+    fn default() -> Self {
+        MazeBuilder::new(0, 0)
+    }
+}
+
+impl MazeBuilder {
+    pub fn new(room_count: usize, prune_passes: usize) -> Self {
+        MazeBuilder {
+            room_count,
+            min_room_size: 3,
+            max_room_size: 7,
+            prune_passes,
+            rooms: Vec::new(),
+            starting_position: (1, 1),
+            spawn_regions: Vec::new(),
+        }
+    }
+
+    fn carve_maze(&self, map: &mut Map, rng: &mut StdRng) {
+        let grid_w = (map.cols - 1) / 2;
+        let grid_h = (map.rows - 1) / 2;
+        let mut visited = vec![false; grid_w * grid_h];
+        let mut stack = vec![(0, 0)];
+
+        visited[0] = true;
+        map.set_tile(1, 1, Tile::Floor);
+
+        while let Some(&(cx, cy)) = stack.last() {
+            let mut neighbours = Vec::with_capacity(4);
+            if cx > 0 && !visited[cy * grid_w + cx - 1] {
+                neighbours.push((cx - 1, cy));
+            }
+            if cx + 1 < grid_w && !visited[cy * grid_w + cx + 1] {
+                neighbours.push((cx + 1, cy));
+            }
+            if cy > 0 && !visited[(cy - 1) * grid_w + cx] {
+                neighbours.push((cx, cy - 1));
+            }
+            if cy + 1 < grid_h && !visited[(cy + 1) * grid_w + cx] {
+                neighbours.push((cx, cy + 1));
+            }
+
+            if neighbours.is_empty() {
+                stack.pop();
+                continue;
+            }
+
+            let (nx, ny) = neighbours[rng.gen_range(0..neighbours.len())];
+            visited[ny * grid_w + nx] = true;
+            // Knock down the wall between the two cells and open the next one
+            map.set_tile(cx + nx + 1, cy + ny + 1, Tile::Floor);
+            map.set_tile(nx * 2 + 1, ny * 2 + 1, Tile::Floor);
+            stack.push((nx, ny));
+        }
+    }
+
+    fn carve_rooms(&mut self, map: &mut Map, rng: &mut StdRng) {
+        let room_count = if self.room_count == 0 {
+            0
+        } else {
+            scale_with_area(map, self.room_count)
+        };
+        for _ in 0..room_count {
+            let w = rng.gen_range(self.min_room_size..=self.max_room_size);
+            let h = rng.gen_range(self.min_room_size..=self.max_room_size);
+            let x = rng.gen_range(1..(map.cols - w - 1));
+            let y = rng.gen_range(1..(map.rows - h - 1));
+
+            let room = Rect::new(x, y, w, h);
+            if self.rooms.iter().any(|other| room.intersect(other)) {
+                continue;
+            }
+
+            map.set_rect(&room, Tile::Floor);
+            self.rooms.push(room);
+        }
+    }
+
+    fn prune_dead_ends(&self, map: &mut Map) {
+        for _ in 0..self.prune_passes {
+            let mut dead_ends = Vec::new();
+            for y in 1..map.rows - 1 {
+                for x in 1..map.cols - 1 {
+                    if (x, y) == self.starting_position || map.get_tile(x, y) != Tile::Floor {
+                        continue;
+                    }
+                    let exits = [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+                        .iter()
+                        .filter(|(nx, ny)| map.get_tile(*nx, *ny) == Tile::Floor)
+                        .count();
+                    if exits <= 1 {
+                        dead_ends.push((x, y));
+                    }
+                }
+            }
+
+            if dead_ends.is_empty() {
+                break;
+            }
+            for (x, y) in dead_ends {
+                map.set_tile(x, y, Tile::Wall);
+            }
+        }
+    }
+}
+
+impl MapBuilder for MazeBuilder {
+    fn build_map(&mut self, map: &mut Map, rng: &mut StdRng) {
+        self.carve_maze(map, rng);
+        self.carve_rooms(map, rng);
+
+        if let Some(room) = self.rooms.first() {
+            self.starting_position = room.center();
+        }
+        self.prune_dead_ends(map);
+        cull_unreachable(map, self.starting_position);
+
+        self.spawn_regions = if self.rooms.is_empty() {
+            floor_chunks(map, self.starting_position)
+        } else {
+            rooms_to_spawn_regions(map, &self.rooms)
+        };
+    }
+
+    fn get_rooms(&self) -> Vec<Rect> {
+        self.rooms.clone()
+    }
+
+    fn get_starting_position(&self) -> (usize, usize) {
+        self.starting_position
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        self.spawn_regions.clone()
+    }
+}
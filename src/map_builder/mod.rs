@@ -0,0 +1,342 @@
+use std::ops::RangeInclusive;
+
+use bevy::prelude::*;
+use bracket_pathfinding::prelude::DijkstraMap;
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use crate::map::{Map, Rect, Tile};
+
+mod bsp;
+mod cellular_automata;
+mod dla;
+mod doors;
+mod drunkard;
+mod maze;
+mod prefab;
+mod rooms_and_corridors;
+mod validation;
+mod wfc;
+
+pub use bsp::BspBuilder;
+pub use cellular_automata::CellularAutomataBuilder;
+pub use dla::{DlaAlgorithm, DlaBuilder};
+pub use drunkard::DrunkardsWalkBuilder;
+pub use maze::MazeBuilder;
+pub use prefab::PrefabBuilder;
+pub use rooms_and_corridors::RoomsAndCorridorsBuilder;
+pub use validation::{validate_map, MapValidationError};
+pub use wfc::{WaveFunctionCollapseBuilder, WfcSource};
+
+pub struct MapBuilderPlugin;
+
+/// This plugin registers the built-in map generators
+/// Other plugins can add their own generators through the `MapBuilders` resource
+impl Plugin for MapBuilderPlugin {
+    fn build(&self, app: &mut App) {
+        let mut builders = MapBuilders::default();
+        builders.register("Rooms and corridors", 1..=usize::MAX, || {
+            Box::new(RoomsAndCorridorsBuilder::default())
+        });
+        builders.register("Binary space partition", 1..=usize::MAX, || {
+            Box::new(BspBuilder::default())
+        });
+        builders.register("Cellular automata caves", 1..=usize::MAX, || {
+            Box::new(CellularAutomataBuilder::default())
+        });
+        builders.register("Drunkard's walk: open area", 1..=usize::MAX, || {
+            Box::new(DrunkardsWalkBuilder::open_area())
+        });
+        builders.register("Drunkard's walk: open halls", 1..=usize::MAX, || {
+            Box::new(DrunkardsWalkBuilder::open_halls())
+        });
+        builders.register("Drunkard's walk: winding passages", 1..=usize::MAX, || {
+            Box::new(DrunkardsWalkBuilder::winding_passages())
+        });
+        builders.register("DLA: walk inwards", 1..=usize::MAX, || {
+            Box::new(DlaBuilder::new(DlaAlgorithm::WalkInwards))
+        });
+        builders.register("DLA: walk outwards", 1..=usize::MAX, || {
+            Box::new(DlaBuilder::new(DlaAlgorithm::WalkOutwards))
+        });
+        builders.register("DLA: central attractor", 1..=usize::MAX, || {
+            Box::new(DlaBuilder::new(DlaAlgorithm::CentralAttractor))
+        });
+        builders.register("Maze", 1..=usize::MAX, || Box::new(MazeBuilder::default()));
+        builders.register("Maze with rooms", 1..=usize::MAX, || {
+            Box::new(MazeBuilder::new(6, 8))
+        });
+        builders.register("Wave function collapse: sample", 1..=usize::MAX, || {
+            Box::new(WaveFunctionCollapseBuilder::default())
+        });
+        builders.register("Wave function collapse: caves", 1..=usize::MAX, || {
+            Box::new(WaveFunctionCollapseBuilder::new(
+                WfcSource::Builder(|| Box::new(CellularAutomataBuilder::default())),
+                8,
+            ))
+        });
+
+        app.insert_resource(builders);
+    }
+}
+
+/// A map generation algorithm
+/// `build_map` writes the tiles into the map, the other methods report what was generated
+pub trait MapBuilder: Send + Sync {
+    fn build_map(&mut self, map: &mut Map, rng: &mut StdRng);
+    fn get_rooms(&self) -> Vec<Rect>;
+    fn get_starting_position(&self) -> (usize, usize);
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>>;
+
+    /// Things to spawn at fixed tiles, on top of what is spawned in the spawn regions
+    fn get_spawn_list(&self) -> Vec<(usize, String)> {
+        Vec::new()
+    }
+}
+
+struct MapBuilderEntry {
+    name: &'static str,
+    depths: RangeInclusive<usize>,
+    factory: fn() -> Box<dyn MapBuilder>,
+}
+
+/// Registry of the map generators, `spawn_map` picks one of them for the current depth
+#[derive(Resource, Default)]
+pub struct MapBuilders {
+    entries: Vec<MapBuilderEntry>,
+}
+
+impl MapBuilders {
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        depths: RangeInclusive<usize>,
+        factory: fn() -> Box<dyn MapBuilder>,
+    ) -> &mut Self {
+        self.entries.push(MapBuilderEntry {
+            name,
+            depths,
+            factory,
+        });
+        self
+    }
+
+    pub fn builder_for(&self, depth: usize, rng: &mut StdRng) -> Option<Box<dyn MapBuilder>> {
+        let candidates: Vec<&MapBuilderEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.depths.contains(&depth))
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+
+        let entry = candidates[rng.gen_range(0..candidates.len())];
+        info!("Building map of depth {} with: {}", depth, entry.name);
+
+        // Every level gets a chance of vaults, whatever generated it
+        Some(Box::new(PrefabBuilder::new((entry.factory)())))
+    }
+
+    /// Builds the map for the depth, starting over with another pick when validation fails
+    /// Once every pick has been rejected a plain maze is built, it always connects the whole map
+    pub fn build(&self, depth: usize, map: &mut Map, rng: &mut StdRng) {
+        for attempt in 1..=MAX_BUILD_ATTEMPTS {
+            let Some(mut builder) = self.builder_for(depth, rng) else {
+                error!("No map builder registered for depth {}", depth);
+                break;
+            };
+
+            map.clear_map();
+            match build_with(builder.as_mut(), map, rng) {
+                Ok(()) => return,
+                Err(err) => warn!("Map rejected on attempt {}: {}", attempt, err),
+            }
+        }
+
+        warn!(
+            "Could not build a valid map of depth {}, falling back to a maze",
+            depth
+        );
+        map.clear_map();
+        if let Err(err) = build_with(&mut MazeBuilder::default(), map, rng) {
+            error!("The fallback maze was rejected too: {}", err);
+        }
+    }
+}
+
+/// Runs the builder on the map and stores its results into the map
+/// Fails when the map doesn't pass `validate_map`
+pub fn build_with(
+    builder: &mut dyn MapBuilder,
+    map: &mut Map,
+    rng: &mut StdRng,
+) -> Result<(), MapValidationError> {
+    builder.build_map(map, rng);
+    map.rooms = builder.get_rooms();
+    map.player_start = builder.get_starting_position();
+    map.spawn_regions = builder.get_spawn_regions();
+    map.spawn_list = builder.get_spawn_list();
+    doors::place_doors(map, rng);
+    place_down_stairs(map);
+    validate_map(map)?;
+    // Only once the map is known to be sound, the keys are placed on the reachable side
+    doors::lock_doors(map, rng);
+    Ok(())
+}
+
+/// Puts the stairs down on the floor tile the furthest away from the player start
+pub(crate) fn place_down_stairs(map: &mut Map) {
+    map.populate_blocked();
+
+    let start_idx = map.xy_to_index(map.player_start.0, map.player_start.1);
+    let dijkstra = distances_from(map, start_idx);
+
+    let furthest = dijkstra
+        .map
+        .iter()
+        .enumerate()
+        .filter(|(idx, distance)| {
+            *idx != start_idx
+                && **distance < f32::MAX
+                && map.get_tile(idx % map.cols, idx / map.cols) == Tile::Floor
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(idx, _)| idx);
+
+    if let Some(idx) = furthest {
+        map.set_tile(idx % map.cols, idx / map.cols, Tile::DownStairs);
+    } else {
+        warn!("No room for the stairs down");
+    }
+}
+
+/// Every room except the first one (where the player starts) becomes a spawn region
+pub(crate) fn rooms_to_spawn_regions(map: &Map, rooms: &[Rect]) -> Vec<Vec<usize>> {
+    rooms
+        .iter()
+        .skip(1)
+        .map(|room| {
+            let mut region = Vec::new();
+            for y in room.y1..=room.y2 {
+                for x in room.x1..=room.x2 {
+                    region.push(map.xy_to_index(x, y));
+                }
+            }
+            region
+        })
+        .collect()
+}
+
+/// Maps thrown away before giving up on a level
+const MAX_BUILD_ATTEMPTS: usize = 10;
+
+/// Size of the chunks used to split open areas into spawn regions
+const SPAWN_CHUNK_SIZE: usize = 8;
+
+/// Map size the builders are tuned for, counts like rooms grow with the area of bigger maps
+const REFERENCE_SIZE: (usize, usize) = (80, 45);
+
+/// A diagonal step onto lava, the most a single step can cost
+const MAX_STEP_COST: f32 = 1.45 * 10.0;
+
+/// Distances from `start_idx` over the walkable tiles, `f32::MAX` where it can't be reached
+/// The search goes deep enough to cross the whole map, so it never gives up on a reachable tile
+pub(crate) fn distances_from(map: &Map, start_idx: usize) -> DijkstraMap {
+    let max_depth = (map.cols * map.rows) as f32 * MAX_STEP_COST;
+    DijkstraMap::new(map.cols, map.rows, &[start_idx], map, max_depth)
+}
+
+/// Turns every tile that can't be reached from `start` into a wall
+pub(crate) fn cull_unreachable(map: &mut Map, start: (usize, usize)) {
+    map.populate_blocked();
+
+    let start_idx = map.xy_to_index(start.0, start.1);
+    let dijkstra = distances_from(map, start_idx);
+
+    for y in 0..map.rows {
+        for x in 0..map.cols {
+            let idx = map.xy_to_index(x, y);
+            if idx != start_idx && map.get_tile(x, y) != Tile::Wall && dijkstra.map[idx] == f32::MAX
+            {
+                map.set_tile(x, y, Tile::Wall);
+            }
+        }
+    }
+
+    map.populate_blocked();
+}
+
+/// Finds the floor tile closest to the given position
+pub(crate) fn nearest_floor(map: &Map, x: usize, y: usize) -> (usize, usize) {
+    let mut nearest = (x, y);
+    let mut nearest_distance = usize::MAX;
+
+    for ty in 0..map.rows {
+        for tx in 0..map.cols {
+            if map.get_tile(tx, ty) != Tile::Floor {
+                continue;
+            }
+            let distance = tx.abs_diff(x).pow(2) + ty.abs_diff(y).pow(2);
+            if distance < nearest_distance {
+                nearest = (tx, ty);
+                nearest_distance = distance;
+            }
+        }
+    }
+
+    nearest
+}
+
+/// Splits the floor into square chunks to be used as spawn regions for maps without rooms
+/// The chunk containing the starting position is left out
+pub(crate) fn floor_chunks(map: &Map, start: (usize, usize)) -> Vec<Vec<usize>> {
+    let chunks_x = map.cols.div_ceil(SPAWN_CHUNK_SIZE);
+    let chunks_y = map.rows.div_ceil(SPAWN_CHUNK_SIZE);
+    let mut regions = vec![Vec::new(); chunks_x * chunks_y];
+
+    for y in 0..map.rows {
+        for x in 0..map.cols {
+            if map.get_tile(x, y) == Tile::Floor {
+                let chunk = (y / SPAWN_CHUNK_SIZE) * chunks_x + x / SPAWN_CHUNK_SIZE;
+                regions[chunk].push(map.xy_to_index(x, y));
+            }
+        }
+    }
+
+    let start_chunk = (start.1 / SPAWN_CHUNK_SIZE) * chunks_x + start.0 / SPAWN_CHUNK_SIZE;
+    regions
+        .into_iter()
+        .enumerate()
+        .filter(|(i, region)| *i != start_chunk && !region.is_empty())
+        .map(|(_, region)| region)
+        .collect()
+}
+
+pub(crate) fn floor_count(map: &Map) -> usize {
+    let mut count = 0;
+    for y in 0..map.rows {
+        for x in 0..map.cols {
+            if map.get_tile(x, y) == Tile::Floor {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+/// Scales a count tuned for an 80x45 map to the area of the given map
+pub(crate) fn scale_with_area(map: &Map, count: usize) -> usize {
+    (count * map.cols * map.rows / (REFERENCE_SIZE.0 * REFERENCE_SIZE.1)).max(1)
+}
+
+/// Moves one tile in a random cardinal direction, staying inside the map border
+pub(crate) fn random_step(map: &Map, rng: &mut StdRng, x: &mut usize, y: &mut usize) {
+    match rng.gen_range(0..4) {
+        0 if *x > 1 => *x -= 1,
+        1 if *x < map.cols - 2 => *x += 1,
+        2 if *y > 1 => *y -= 1,
+        3 if *y < map.rows - 2 => *y += 1,
+        _ => {}
+    }
+}
@@ -0,0 +1,222 @@
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use super::{distances_from, MapBuilder};
+use crate::map::{Map, Rect, Tile};
+
+/// A hand-authored set piece
+/// `#` is a wall, `.` a floor, `+` a door, `~` shallow water, `%` lava, `=` a bridge and `^` a trap,
+/// the other characters are floors with something spawned on them
+pub struct Prefab {
+    pub name: &'static str,
+    pub template: &'static str,
+}
+
+pub const GOBLIN_CAMP: Prefab = Prefab {
+    name: "Goblin camp",
+    template: include_str!("prefabs/goblin_camp.txt"),
+};
+
+pub const ORC_DEN: Prefab = Prefab {
+    name: "Orc den",
+    template: include_str!("prefabs/orc_den.txt"),
+};
+
+pub const POTION_VAULT: Prefab = Prefab {
+    name: "Potion vault",
+    template: include_str!("prefabs/potion_vault.txt"),
+};
+
+pub const LAVA_MOAT: Prefab = Prefab {
+    name: "Lava moat",
+    template: include_str!("prefabs/lava_moat.txt"),
+};
+
+pub const FLOODED_HALL: Prefab = Prefab {
+    name: "Flooded hall",
+    template: include_str!("prefabs/flooded_hall.txt"),
+};
+
+fn template_tile(c: char) -> Tile {
+    match c {
+        '#' => Tile::Wall,
+        '+' => Tile::DoorClosed,
+        '~' => Tile::ShallowWater,
+        '%' => Tile::Lava,
+        '=' => Tile::Bridge,
+        '^' => Tile::Trap,
+        _ => Tile::Floor,
+    }
+}
+
+fn spawn_name(c: char) -> Option<&'static str> {
+    match c {
+        'g' => Some("Goblin"),
+        'o' => Some("Orc"),
+        '!' => Some("Health Potion"),
+        _ => None,
+    }
+}
+
+impl Prefab {
+    /// Template rows from the bottom up, to match the map coordinates
+    fn rows(&self) -> Vec<Vec<char>> {
+        self.template
+            .lines()
+            .filter(|line| !line.is_empty())
+            .rev()
+            .map(|line| line.chars().collect())
+            .collect()
+    }
+}
+
+/// Builds the map with another builder and stamps prefab vaults into its open areas
+pub struct PrefabBuilder {
+    parent: Box<dyn MapBuilder>,
+    pub prefabs: Vec<Prefab>,
+    pub max_vaults: usize,
+    /// Random locations tried for every vault
+    pub placement_attempts: usize,
+    vaults: Vec<Rect>,
+    vault_tiles: Vec<usize>,
+    spawn_list: Vec<(usize, String)>,
+}
+
+impl PrefabBuilder {
+    pub fn new(parent: Box<dyn MapBuilder>) -> Self {
+        PrefabBuilder {
+            parent,
+            prefabs: vec![GOBLIN_CAMP, ORC_DEN, POTION_VAULT, LAVA_MOAT, FLOODED_HALL],
+            max_vaults: 2,
+            placement_attempts: 50,
+            vaults: Vec::new(),
+            vault_tiles: Vec::new(),
+            spawn_list: Vec::new(),
+        }
+    }
+
+    /// A vault only goes where every tile under it is already floor,
+    /// away from the player and the other vaults
+    fn can_place(&self, map: &Map, area: &Rect) -> bool {
+        let start = self.parent.get_starting_position();
+        if (area.x1..=area.x2).contains(&start.0) && (area.y1..=area.y2).contains(&start.1) {
+            return false;
+        }
+        if self.vaults.iter().any(|vault| vault.intersect(area)) {
+            return false;
+        }
+
+        (area.y1..=area.y2).all(|y| (area.x1..=area.x2).all(|x| map.get_tile(x, y) == Tile::Floor))
+    }
+
+    fn stamp(&mut self, map: &mut Map, prefab: &Prefab, rng: &mut StdRng) {
+        let rows = prefab.rows();
+        let h = rows.len();
+        let w = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        if w == 0 || w + 2 >= map.cols || h + 2 >= map.rows {
+            return;
+        }
+
+        for _ in 0..self.placement_attempts {
+            let x = rng.gen_range(1..map.cols - w - 1);
+            let y = rng.gen_range(1..map.rows - h - 1);
+            let area = Rect::new(x, y, w - 1, h - 1);
+            if !self.can_place(map, &area) {
+                continue;
+            }
+
+            let reachable_before = reachable_count(map, self.parent.get_starting_position());
+            let original = map.clone();
+            let mut spawns = Vec::new();
+            for (dy, row) in rows.iter().enumerate() {
+                for (dx, c) in row.iter().enumerate() {
+                    let (tx, ty) = (x + dx, y + dy);
+                    let tile = template_tile(*c);
+                    map.set_tile(tx, ty, tile);
+                    if tile != Tile::Floor {
+                        continue;
+                    }
+
+                    if let Some(name) = spawn_name(*c) {
+                        spawns.push((map.xy_to_index(tx, ty), name.to_string()));
+                    } else if *c != '.' {
+                        warn!("Unknown character '{}' in prefab {}", c, prefab.name);
+                    }
+                }
+            }
+
+            // The vault walls must not cut anything off, only its own walls may go missing
+            let vault_walls = rows
+                .iter()
+                .flatten()
+                .filter(|c| !template_tile(**c).is_walkable())
+                .count();
+            if reachable_count(map, self.parent.get_starting_position()) + vault_walls
+                < reachable_before
+            {
+                *map = original;
+                continue;
+            }
+
+            info!("Placed {} at ({}, {})", prefab.name, x, y);
+            self.vaults.push(area);
+            for ty in area.y1..=area.y2 {
+                for tx in area.x1..=area.x2 {
+                    self.vault_tiles.push(map.xy_to_index(tx, ty));
+                }
+            }
+            self.spawn_list.extend(spawns);
+            return;
+        }
+    }
+}
+
+impl MapBuilder for PrefabBuilder {
+    fn build_map(&mut self, map: &mut Map, rng: &mut StdRng) {
+        self.parent.build_map(map, rng);
+
+        let prefabs = std::mem::take(&mut self.prefabs);
+        for _ in 0..self.max_vaults {
+            let prefab = &prefabs[rng.gen_range(0..prefabs.len())];
+            self.stamp(map, prefab, rng);
+        }
+        self.prefabs = prefabs;
+    }
+
+    fn get_rooms(&self) -> Vec<Rect> {
+        self.parent.get_rooms()
+    }
+
+    fn get_starting_position(&self) -> (usize, usize) {
+        self.parent.get_starting_position()
+    }
+
+    /// The vaults bring their own spawns, so they are left out of the spawn regions
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        self.parent
+            .get_spawn_regions()
+            .into_iter()
+            .map(|mut region| {
+                region.retain(|idx| !self.vault_tiles.contains(idx));
+                region
+            })
+            .filter(|region| !region.is_empty())
+            .collect()
+    }
+
+    fn get_spawn_list(&self) -> Vec<(usize, String)> {
+        let mut spawn_list = self.parent.get_spawn_list();
+        spawn_list.extend(self.spawn_list.iter().cloned());
+        spawn_list
+    }
+}
+
+fn reachable_count(map: &Map, start: (usize, usize)) -> usize {
+    let mut map = map.clone();
+    map.populate_blocked();
+
+    let start_idx = map.xy_to_index(start.0, start.1);
+    let dijkstra = distances_from(&map, start_idx);
+    dijkstra.map.iter().filter(|d| **d < f32::MAX).count()
+}
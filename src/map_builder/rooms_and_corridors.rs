@@ -0,0 +1,85 @@
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use super::{rooms_to_spawn_regions, scale_with_area, MapBuilder};
+use crate::map::{Map, Rect, Tile};
+
+pub struct RoomsAndCorridorsBuilder {
+    /// Rooms tried on an 80x45 map, bigger maps try more of them
+    pub max_rooms: usize,
+    pub min_size: usize,
+    pub max_size: usize,
+    rooms: Vec<Rect>,
+    spawn_regions: Vec<Vec<usize>>,
+}
+
+impl Default for RoomsAndCorridorsBuilder {
+    fn default() -> Self {
+        RoomsAndCorridorsBuilder {
+            max_rooms: 20,
+            min_size: 6,
+            max_size: 10,
+            rooms: Vec::new(),
+            spawn_regions: Vec::new(),
+        }
+    }
+}
+
+impl MapBuilder for RoomsAndCorridorsBuilder {
+    fn build_map(&mut self, map: &mut Map, rng: &mut StdRng) {
+        let cols = map.cols;
+        let rows = map.rows;
+
+        map.set_horizontal_line(0, cols - 1, 0, Tile::Wall);
+        map.set_horizontal_line(0, cols - 1, rows - 1, Tile::Wall);
+        map.set_vertical_line(0, 0, rows - 1, Tile::Wall);
+        map.set_vertical_line(cols - 1, 0, rows - 1, Tile::Wall);
+
+        for _ in 0..scale_with_area(map, self.max_rooms) {
+            let w = rng.gen_range(self.min_size..self.max_size);
+            let h = rng.gen_range(self.min_size..self.max_size);
+            let x = rng.gen_range(1..(cols - w - 1));
+            let y = rng.gen_range(1..(rows - h - 1));
+
+            let new_room = Rect::new(x, y, w, h);
+            let mut ok = true;
+            for other_room in &self.rooms {
+                if new_room.intersect(other_room) {
+                    ok = false;
+                }
+            }
+
+            if ok {
+                map.set_rect(&new_room, Tile::Floor);
+
+                if !self.rooms.is_empty() {
+                    let (new_x, new_y) = new_room.center();
+                    let (prev_x, prev_y) = self.rooms[self.rooms.len() - 1].center();
+                    if rng.gen_range(0..2) == 1 {
+                        map.set_horizontal_line(prev_x, new_x, prev_y, Tile::Floor);
+                        map.set_vertical_line(new_x, prev_y, new_y, Tile::Floor);
+                    } else {
+                        map.set_vertical_line(prev_x, prev_y, new_y, Tile::Floor);
+                        map.set_horizontal_line(prev_x, new_x, new_y, Tile::Floor);
+                    }
+                }
+
+                self.rooms.push(new_room);
+            }
+        }
+
+        self.spawn_regions = rooms_to_spawn_regions(map, &self.rooms);
+    }
+
+    fn get_rooms(&self) -> Vec<Rect> {
+        self.rooms.clone()
+    }
+
+    fn get_starting_position(&self) -> (usize, usize) {
+        self.rooms[0].center()
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        self.spawn_regions.clone()
+    }
+}
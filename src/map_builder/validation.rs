@@ -0,0 +1,176 @@
+use std::fmt;
+
+use super::{cull_unreachable, distances_from};
+use crate::map::{Map, Tile};
+
+/// Share of the map that has to be reachable floor for the map to be played
+pub const MIN_FLOOR_FRACTION: f32 = 0.12;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapValidationError {
+    StartBlocked,
+    NotEnoughFloor { floor: usize, required: usize },
+    NoStairs,
+    NoPathToStairs,
+}
+
+impl fmt::Display for MapValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapValidationError::StartBlocked => write!(f, "the player can't stand on the start"),
+            MapValidationError::NotEnoughFloor { floor, required } => {
+                write!(
+                    f,
+                    "only {} reachable floor tiles, {} required",
+                    floor, required
+                )
+            }
+            MapValidationError::NoStairs => write!(f, "there are no stairs down"),
+            MapValidationError::NoPathToStairs => write!(f, "the stairs down can't be reached"),
+        }
+    }
+}
+
+/// Checks that the map can be played from `Map::player_start`
+/// Everything that can't be reached from the start is turned into wall on the way,
+/// the map is rejected when too little floor is left or the stairs down are out of reach
+pub fn validate_map(map: &mut Map) -> Result<(), MapValidationError> {
+    let (start_x, start_y) = map.player_start;
+    if start_x >= map.cols || start_y >= map.rows || !map.get_tile(start_x, start_y).is_walkable() {
+        return Err(MapValidationError::StartBlocked);
+    }
+
+    cull_unreachable(map, map.player_start);
+    let walls: Vec<bool> = (0..map.cols * map.rows)
+        .map(|idx| map.get_tile(idx % map.cols, idx / map.cols) == Tile::Wall)
+        .collect();
+    map.spawn_regions
+        .iter_mut()
+        .for_each(|region| region.retain(|idx| !walls[*idx]));
+    map.spawn_regions.retain(|region| !region.is_empty());
+    map.spawn_list.retain(|(idx, _)| !walls[*idx]);
+
+    let floor = (0..map.rows)
+        .flat_map(|y| (0..map.cols).map(move |x| (x, y)))
+        .filter(|(x, y)| map.get_tile(*x, *y) != Tile::Wall)
+        .count();
+    let required = (map.cols as f32 * map.rows as f32 * MIN_FLOOR_FRACTION) as usize;
+    if floor < required {
+        return Err(MapValidationError::NotEnoughFloor { floor, required });
+    }
+
+    let Some((stairs_x, stairs_y)) = map.find_tile(Tile::DownStairs) else {
+        return Err(MapValidationError::NoStairs);
+    };
+    let start_idx = map.xy_to_index(start_x, start_y);
+    let dijkstra = distances_from(map, start_idx);
+    if dijkstra.map[map.xy_to_index(stairs_x, stairs_y)] == f32::MAX {
+        return Err(MapValidationError::NoPathToStairs);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::super::{
+        build_with, MapBuilder, MapBuilderPlugin, MapBuilders, PrefabBuilder, MAX_BUILD_ATTEMPTS,
+    };
+    use super::validate_map;
+    use crate::map::{Map, Rect, Tile};
+
+    const SEEDS: u64 = 30;
+
+    fn registered_builders() -> MapBuilders {
+        let mut app = App::new();
+        app.add_plugins(MapBuilderPlugin);
+        app.world.remove_resource::<MapBuilders>().unwrap()
+    }
+
+    /// Builds a map the way `MapBuilders::build` does, with a single generator
+    fn build(builders: &MapBuilders, entry: usize, seed: u64) -> Map {
+        let entry = &builders.entries[entry];
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut map = Map::new(80, 45, 16);
+        for _ in 0..MAX_BUILD_ATTEMPTS {
+            map.clear_map();
+            let mut builder = PrefabBuilder::new((entry.factory)());
+            if build_with(&mut builder, &mut map, &mut rng).is_ok() {
+                return map;
+            }
+        }
+        panic!("{} never passed validation with seed {}", entry.name, seed);
+    }
+
+    fn tiles(map: &Map) -> Vec<Tile> {
+        (0..map.rows)
+            .flat_map(|y| (0..map.cols).map(move |x| map.get_tile(x, y)))
+            .collect()
+    }
+
+    #[test]
+    fn every_builder_makes_valid_maps() {
+        let builders = registered_builders();
+        for entry in 0..builders.entries.len() {
+            for seed in 0..SEEDS {
+                let mut map = build(&builders, entry, seed);
+                // Every key can be reached, so in the end the player gets through all the doors
+                while let Some((x, y)) = map.find_tile(Tile::DoorLocked) {
+                    map.set_tile(x, y, Tile::DoorClosed);
+                }
+                let name = builders.entries[entry].name;
+                assert_eq!(validate_map(&mut map), Ok(()), "{} seed {}", name, seed);
+            }
+        }
+    }
+
+    #[test]
+    fn every_builder_is_deterministic() {
+        let builders = registered_builders();
+        for entry in 0..builders.entries.len() {
+            for seed in 0..SEEDS {
+                let first = build(&builders, entry, seed);
+                let second = build(&builders, entry, seed);
+                let name = builders.entries[entry].name;
+                assert_eq!(tiles(&first), tiles(&second), "{} seed {}", name, seed);
+                assert_eq!(first.player_start, second.player_start, "{}", name);
+                assert_eq!(first.spawn_regions, second.spawn_regions, "{}", name);
+                assert_eq!(first.spawn_list, second.spawn_list, "{}", name);
+            }
+        }
+    }
+
+    /// Leaves the map as solid rock, it never passes validation
+    struct SolidRock;
+
+    impl MapBuilder for SolidRock {
+        fn build_map(&mut self, _map: &mut Map, _rng: &mut StdRng) {}
+
+        fn get_rooms(&self) -> Vec<Rect> {
+            Vec::new()
+        }
+
+        fn get_starting_position(&self) -> (usize, usize) {
+            (1, 1)
+        }
+
+        fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+            Vec::new()
+        }
+    }
+
+    #[test]
+    fn rejected_maps_fall_back_to_a_playable_one() {
+        let mut builders = MapBuilders::default();
+        builders.register("Solid rock", 1..=usize::MAX, || Box::new(SolidRock));
+
+        let mut map = Map::new(80, 45, 16);
+        builders.build(1, &mut map, &mut StdRng::seed_from_u64(0));
+        assert!(map.find_tile(Tile::DownStairs).is_some());
+        assert_eq!(validate_map(&mut map), Ok(()));
+    }
+}
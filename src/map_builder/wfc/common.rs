@@ -0,0 +1,125 @@
+use crate::map::{Map, Tile};
+
+pub const NORTH: usize = 0;
+pub const SOUTH: usize = 1;
+pub const WEST: usize = 2;
+pub const EAST: usize = 3;
+
+/// A square piece of a source map and the pieces allowed next to it
+#[derive(Clone, Debug)]
+pub struct MapChunk {
+    /// Tiles of the chunk, row by row from the bottom
+    pub pattern: Vec<Tile>,
+    /// How many times the pattern was found in the source map
+    pub frequency: usize,
+    /// Floor flags along the north, south, west and east edges
+    pub exits: [Vec<bool>; 4],
+    /// Indices of the chunks that fit on each side of this one
+    pub compatible_with: [Vec<usize>; 4],
+}
+
+impl MapChunk {
+    /// Renders the pattern as ASCII art, top row first
+    pub fn render(&self, chunk_size: usize) -> String {
+        let mut text = String::new();
+        for y in (0..chunk_size).rev() {
+            for x in 0..chunk_size {
+                text.push(match self.pattern[y * chunk_size + x] {
+                    Tile::Wall => '#',
+                    _ => '.',
+                });
+            }
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// Builds a map from ASCII art where `#` is a wall and anything else is floor
+/// The first line of the text is the top row of the map
+pub fn ascii_to_map(text: &str, tile_size: usize) -> Map {
+    let lines: Vec<&str> = text.lines().filter(|line| !line.is_empty()).collect();
+    let rows = lines.len();
+    let cols = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let mut map = Map::new(cols, rows, tile_size);
+    for (i, line) in lines.iter().enumerate() {
+        for (x, c) in line.chars().enumerate() {
+            let tile = if c == '#' { Tile::Wall } else { Tile::Floor };
+            map.set_tile(x, rows - 1 - i, tile);
+        }
+    }
+    map
+}
+
+/// Cuts the map into chunks and learns which of them may sit next to each other
+pub fn learn_chunks(map: &Map, chunk_size: usize, include_flipping: bool) -> Vec<MapChunk> {
+    let mut patterns: Vec<(Vec<Tile>, usize)> = Vec::new();
+    let mut add_pattern = |pattern: Vec<Tile>| {
+        if let Some(known) = patterns.iter_mut().find(|(p, _)| *p == pattern) {
+            known.1 += 1;
+        } else {
+            patterns.push((pattern, 1));
+        }
+    };
+
+    for cy in 0..map.rows / chunk_size {
+        for cx in 0..map.cols / chunk_size {
+            let tile_at =
+                |x: usize, y: usize| map.get_tile(cx * chunk_size + x, cy * chunk_size + y);
+            let flips: &[(bool, bool)] = if include_flipping {
+                &[(false, false), (true, false), (false, true), (true, true)]
+            } else {
+                &[(false, false)]
+            };
+
+            for &(flip_x, flip_y) in flips {
+                let mut pattern = Vec::with_capacity(chunk_size * chunk_size);
+                for y in 0..chunk_size {
+                    for x in 0..chunk_size {
+                        let sx = if flip_x { chunk_size - 1 - x } else { x };
+                        let sy = if flip_y { chunk_size - 1 - y } else { y };
+                        pattern.push(tile_at(sx, sy));
+                    }
+                }
+                add_pattern(pattern);
+            }
+        }
+    }
+
+    let mut chunks: Vec<MapChunk> = patterns
+        .into_iter()
+        .map(|(pattern, frequency)| {
+            let exit = |x: usize, y: usize| pattern[y * chunk_size + x] == Tile::Floor;
+            let exits = [
+                (0..chunk_size).map(|x| exit(x, chunk_size - 1)).collect(),
+                (0..chunk_size).map(|x| exit(x, 0)).collect(),
+                (0..chunk_size).map(|y| exit(0, y)).collect(),
+                (0..chunk_size).map(|y| exit(chunk_size - 1, y)).collect(),
+            ];
+            MapChunk {
+                pattern,
+                frequency,
+                exits,
+                compatible_with: Default::default(),
+            }
+        })
+        .collect();
+
+    // Two chunks fit together when the facing edges open onto the same tiles
+    for i in 0..chunks.len() {
+        for j in 0..chunks.len() {
+            for (side, opposite) in [(NORTH, SOUTH), (SOUTH, NORTH), (WEST, EAST), (EAST, WEST)] {
+                if chunks[i].exits[side] == chunks[j].exits[opposite] {
+                    chunks[i].compatible_with[side].push(j);
+                }
+            }
+        }
+    }
+
+    chunks
+}
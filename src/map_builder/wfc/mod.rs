@@ -0,0 +1,160 @@
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+
+use super::{
+    cull_unreachable, floor_chunks, floor_count, nearest_floor, CellularAutomataBuilder,
+    MapBuilder, REFERENCE_SIZE,
+};
+use crate::map::{Map, Rect, Tile};
+
+mod common;
+mod solver;
+
+use common::{ascii_to_map, learn_chunks, MapChunk};
+
+const SAMPLE_MAP: &str = include_str!("wfc_sample.txt");
+
+/// Where the wave function collapse builder learns its chunks from
+pub enum WfcSource {
+    /// ASCII art, `#` for walls and `.` for floors
+    Sample(&'static str),
+    /// A map made by another generator
+    Builder(fn() -> Box<dyn MapBuilder>),
+}
+
+/// Wave function collapse: fills the map with chunks that fit the neighbours they had in the source
+pub struct WaveFunctionCollapseBuilder {
+    pub source: WfcSource,
+    pub chunk_size: usize,
+    pub include_flipping: bool,
+    /// Collapses tried before falling back to a cave map
+    pub max_attempts: usize,
+    /// Minimum fraction of floor to accept a result
+    pub min_floor_percent: f32,
+    chunks: Vec<MapChunk>,
+    starting_position: (usize, usize),
+    spawn_regions: Vec<Vec<usize>>,
+}
+
+impl Default for WaveFunctionCollapseBuilder {
+    fn default() -> Self {
+        WaveFunctionCollapseBuilder::new(WfcSource::Sample(SAMPLE_MAP), 4)
+    }
+}
+
+impl WaveFunctionCollapseBuilder {
+    pub fn new(source: WfcSource, chunk_size: usize) -> Self {
+        WaveFunctionCollapseBuilder {
+            source,
+            chunk_size,
+            include_flipping: true,
+            max_attempts: 10,
+            min_floor_percent: 0.2,
+            chunks: Vec::new(),
+            starting_position: (0, 0),
+            spawn_regions: Vec::new(),
+        }
+    }
+
+    fn source_map(&self, map: &Map, rng: &mut StdRng) -> Map {
+        match self.source {
+            WfcSource::Sample(text) => ascii_to_map(text, map.tile_size),
+            WfcSource::Builder(factory) => {
+                // A bigger source only adds chunk patterns, which slows down the collapse a lot
+                let mut source = Map::new(
+                    map.cols.min(REFERENCE_SIZE.0),
+                    map.rows.min(REFERENCE_SIZE.1),
+                    map.tile_size,
+                );
+                factory().build_map(&mut source, rng);
+                source
+            }
+        }
+    }
+
+    fn try_collapse(&self, map: &mut Map, rng: &mut StdRng) -> bool {
+        let chunks_x = map.cols / self.chunk_size;
+        let chunks_y = map.rows / self.chunk_size;
+        let Some(solution) = solver::solve(&self.chunks, chunks_x, chunks_y, rng) else {
+            return false;
+        };
+
+        for y in 0..map.rows {
+            for x in 0..map.cols {
+                map.set_tile(x, y, Tile::Wall);
+            }
+        }
+        for (cell, &chunk) in solution.iter().enumerate() {
+            let (cx, cy) = (cell % chunks_x, cell / chunks_x);
+            for y in 0..self.chunk_size {
+                for x in 0..self.chunk_size {
+                    let (tx, ty) = (cx * self.chunk_size + x, cy * self.chunk_size + y);
+                    // Keep the map border solid
+                    if tx == 0 || ty == 0 || tx >= map.cols - 1 || ty >= map.rows - 1 {
+                        continue;
+                    }
+                    let tile = self.chunks[chunk].pattern[y * self.chunk_size + x];
+                    map.set_tile(tx, ty, tile);
+                }
+            }
+        }
+
+        if floor_count(map) == 0 {
+            return false;
+        }
+
+        let start = nearest_floor(map, map.cols / 2, map.rows / 2);
+        cull_unreachable(map, start);
+
+        let total = (map.cols * map.rows) as f32;
+        floor_count(map) as f32 >= total * self.min_floor_percent
+    }
+}
+
+impl MapBuilder for WaveFunctionCollapseBuilder {
+    fn build_map(&mut self, map: &mut Map, rng: &mut StdRng) {
+        let source = self.source_map(map, rng);
+        self.chunks = learn_chunks(&source, self.chunk_size, self.include_flipping);
+        info!("WFC learned {} chunk patterns", self.chunks.len());
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            debug!(
+                "Chunk #{} (x{}):\n{}",
+                i,
+                chunk.frequency,
+                chunk.render(self.chunk_size)
+            );
+        }
+
+        for attempt in 1..=self.max_attempts {
+            if self.try_collapse(map, rng) {
+                self.starting_position = nearest_floor(map, map.cols / 2, map.rows / 2);
+                self.spawn_regions = floor_chunks(map, self.starting_position);
+                return;
+            }
+            warn!("WFC attempt {} failed", attempt);
+        }
+
+        warn!("WFC gave up, falling back to a cave map");
+        for y in 0..map.rows {
+            for x in 0..map.cols {
+                map.set_tile(x, y, Tile::Wall);
+            }
+        }
+        let mut fallback = CellularAutomataBuilder::default();
+        fallback.build_map(map, rng);
+        self.starting_position = fallback.get_starting_position();
+        self.spawn_regions = fallback.get_spawn_regions();
+    }
+
+    fn get_rooms(&self) -> Vec<Rect> {
+        Vec::new()
+    }
+
+    fn get_starting_position(&self) -> (usize, usize) {
+        self.starting_position
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        self.spawn_regions.clone()
+    }
+}
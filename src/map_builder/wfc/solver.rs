@@ -0,0 +1,132 @@
+use std::collections::VecDeque;
+
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use super::common::{MapChunk, EAST, NORTH, SOUTH, WEST};
+
+/// Collapses a grid of chunks until every cell has a single chunk left
+/// Returns `None` when some cell runs out of options
+pub fn solve(
+    chunks: &[MapChunk],
+    chunks_x: usize,
+    chunks_y: usize,
+    rng: &mut StdRng,
+) -> Option<Vec<usize>> {
+    let mut possible = vec![vec![true; chunks.len()]; chunks_x * chunks_y];
+    // Options left in every cell, kept up to date instead of counting them over and over
+    let mut counts = vec![chunks.len(); chunks_x * chunks_y];
+
+    loop {
+        // Pick the undecided cell with the fewest options left
+        let mut lowest = usize::MAX;
+        let mut candidates = Vec::new();
+        for (cell, &count) in counts.iter().enumerate() {
+            if count <= 1 {
+                continue;
+            }
+            if count < lowest {
+                lowest = count;
+                candidates.clear();
+            }
+            if count == lowest {
+                candidates.push(cell);
+            }
+        }
+
+        if candidates.is_empty() {
+            break;
+        }
+
+        let cell = candidates[rng.gen_range(0..candidates.len())];
+        let chosen = pick_weighted(chunks, &possible[cell], rng);
+        possible[cell]
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, o)| *o = i == chosen);
+        counts[cell] = 1;
+
+        if !propagate(chunks, &mut possible, &mut counts, chunks_x, chunks_y, cell) {
+            return None;
+        }
+    }
+
+    possible
+        .iter()
+        .map(|options| options.iter().position(|o| *o))
+        .collect()
+}
+
+fn pick_weighted(chunks: &[MapChunk], options: &[bool], rng: &mut StdRng) -> usize {
+    let total: usize = options
+        .iter()
+        .enumerate()
+        .filter(|(_, o)| **o)
+        .map(|(i, _)| chunks[i].frequency)
+        .sum();
+
+    let mut roll = rng.gen_range(0..total);
+    for (i, _) in options.iter().enumerate().filter(|(_, o)| **o) {
+        if roll < chunks[i].frequency {
+            return i;
+        }
+        roll -= chunks[i].frequency;
+    }
+    unreachable!()
+}
+
+/// Removes the options that don't fit with the neighbours anymore, false on a contradiction
+fn propagate(
+    chunks: &[MapChunk],
+    possible: &mut [Vec<bool>],
+    counts: &mut [usize],
+    chunks_x: usize,
+    chunks_y: usize,
+    start: usize,
+) -> bool {
+    let mut queue = VecDeque::from([start]);
+
+    while let Some(cell) = queue.pop_front() {
+        let (x, y) = (cell % chunks_x, cell / chunks_x);
+        let mut neighbours = Vec::with_capacity(4);
+        if y + 1 < chunks_y {
+            neighbours.push((NORTH, cell + chunks_x));
+        }
+        if y > 0 {
+            neighbours.push((SOUTH, cell - chunks_x));
+        }
+        if x > 0 {
+            neighbours.push((WEST, cell - 1));
+        }
+        if x + 1 < chunks_x {
+            neighbours.push((EAST, cell + 1));
+        }
+
+        for (side, neighbour) in neighbours {
+            let mut allowed = vec![false; chunks.len()];
+            for (i, _) in possible[cell].iter().enumerate().filter(|(_, o)| **o) {
+                for &j in &chunks[i].compatible_with[side] {
+                    allowed[j] = true;
+                }
+            }
+
+            let mut changed = false;
+            for (option, allowed) in possible[neighbour].iter_mut().zip(allowed) {
+                if *option && !allowed {
+                    *option = false;
+                    counts[neighbour] -= 1;
+                    changed = true;
+                }
+            }
+
+            if changed {
+                if counts[neighbour] == 0 {
+                    return false;
+                }
+                queue.push_back(neighbour);
+            }
+        }
+    }
+
+    true
+}
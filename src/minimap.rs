@@ -0,0 +1,179 @@
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+
+use crate::actions::{set_movement_actions, Actions};
+use crate::map::{spawn_map, Map, Position, Tile};
+use crate::monster::Monster;
+use crate::player::Player;
+use crate::GameState;
+
+/// Length in pixels of the longest side of the minimap
+const MINIMAP_SIZE: f32 = 192.0;
+
+pub struct MinimapPlugin;
+
+/// This plugin draws an overview of the explored map in a corner of the screen
+/// Every tile is a single pixel of a texture that gets redrawn when the map or someone on it changes
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Playing), setup_minimap.after(spawn_map))
+            .add_systems(OnExit(GameState::Playing), clear_minimap)
+            .add_systems(
+                Update,
+                (toggle_minimap.after(set_movement_actions), draw_minimap)
+                    .chain()
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+#[derive(Component)]
+pub struct MinimapRoot;
+
+/// The texture the minimap is drawn into
+#[derive(Component)]
+pub struct Minimap(pub Handle<Image>);
+
+fn setup_minimap(mut commands: Commands, map: Res<Map>, mut images: ResMut<Assets<Image>>) {
+    let image = images.add(Image::new_fill(
+        Extent3d {
+            width: map.cols as u32,
+            height: map.rows as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    ));
+
+    // Keep the shape of the map, whatever its size
+    let pixel = MINIMAP_SIZE / map.cols.max(map.rows) as f32;
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(8.0),
+                    right: Val::Px(8.0),
+                    border: UiRect::all(Val::Px(2.0)),
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                border_color: Color::WHITE.into(),
+                ..default()
+            },
+            Name::new("MinimapRoot"),
+            MinimapRoot,
+        ))
+        .with_children(|child| {
+            child.spawn((
+                ImageBundle {
+                    style: Style {
+                        width: Val::Px(map.cols as f32 * pixel),
+                        height: Val::Px(map.rows as f32 * pixel),
+                        ..default()
+                    },
+                    image: UiImage::new(image.clone()),
+                    ..default()
+                },
+                Name::new("Minimap"),
+                Minimap(image),
+            ));
+        });
+}
+
+fn clear_minimap(mut commands: Commands, q_root: Query<Entity, With<MinimapRoot>>) {
+    q_root.iter().for_each(|root| {
+        commands.entity(root).despawn_recursive();
+    });
+}
+
+fn toggle_minimap(actions: Res<Actions>, mut q_root: Query<&mut Visibility, With<MinimapRoot>>) {
+    if !actions.toggle_minimap {
+        return;
+    }
+    if let Ok(mut visibility) = q_root.get_single_mut() {
+        *visibility = match *visibility {
+            Visibility::Hidden => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+fn draw_minimap(
+    map: Res<Map>,
+    mut images: ResMut<Assets<Image>>,
+    q_minimap: Query<&Minimap>,
+    q_root: Query<Ref<Visibility>, With<MinimapRoot>>,
+    q_player: Query<&Position, With<Player>>,
+    q_monsters: Query<&Position, With<Monster>>,
+    q_moved: Query<(), Changed<Position>>,
+) {
+    let Ok(minimap) = q_minimap.get_single() else {
+        return;
+    };
+    let Ok(visibility) = q_root.get_single() else {
+        return;
+    };
+    if *visibility == Visibility::Hidden {
+        return;
+    }
+    // Nothing to redraw unless something moved, the map changed or the minimap just showed up
+    if !map.is_changed() && q_moved.is_empty() && !visibility.is_changed() {
+        return;
+    }
+    let Some(image) = images.get_mut(&minimap.0) else {
+        return;
+    };
+
+    let mut set_pixel = |x: usize, y: usize, color: Color| {
+        // The map starts at the bottom, the texture at the top
+        let pixel = ((map.rows - 1 - y) * map.cols + x) * 4;
+        image.data[pixel..pixel + 4].copy_from_slice(&color.as_rgba_u8());
+    };
+
+    for y in 0..map.rows {
+        for x in 0..map.cols {
+            let idx = map.xy_to_index(x, y);
+            let color = if map.revealed_tiles[idx] {
+                tile_color(map.get_tile(x, y), map.visible_tiles[idx])
+            } else {
+                Color::NONE
+            };
+            set_pixel(x, y, color);
+        }
+    }
+
+    q_monsters
+        .iter()
+        .filter(|pos| map.visible_tiles[map.xy_to_index(pos.x, pos.y)])
+        .for_each(|pos| set_pixel(pos.x, pos.y, Color::RED));
+
+    if let Ok(pos) = q_player.get_single() {
+        set_pixel(pos.x, pos.y, Color::GREEN);
+    }
+}
+
+/// Tiles seen before but out of view are drawn darker, the stairs stay easy to spot
+fn tile_color(tile: Tile, visible: bool) -> Color {
+    let color = match tile {
+        Tile::Floor => Color::rgb(0.45, 0.45, 0.45),
+        Tile::Wall => Color::rgb(0.8, 0.8, 0.8),
+        Tile::DoorClosed | Tile::DoorOpen => Color::rgb(0.6, 0.4, 0.2),
+        Tile::DoorLocked => Color::rgb(0.8, 0.6, 0.1),
+        Tile::ShallowWater => Color::rgb(0.2, 0.4, 0.9),
+        Tile::Lava => Color::rgb(1.0, 0.4, 0.0),
+        Tile::Bridge => Color::rgb(0.5, 0.35, 0.2),
+        Tile::Trap => Color::rgb(0.7, 0.2, 0.7),
+        Tile::DownStairs | Tile::UpStairs => return Color::YELLOW,
+    };
+
+    if visible {
+        color
+    } else {
+        Color::rgb(color.r() * 0.5, color.g() * 0.5, color.b() * 0.5)
+    }
+}
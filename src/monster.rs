@@ -1,13 +1,20 @@
 use bevy::prelude::*;
 use bracket_pathfinding::prelude::*;
-use rand::rngs::ThreadRng;
 use rand::Rng;
 
 use crate::combat::{CombatStats, WantsToMelee};
+use crate::dijkstra::{update_dijkstra_maps, DijkstraMaps};
+use crate::door::OperateDoor;
+use crate::item::{spawn_item_in_backpack, InBackpack, ProvidesHealing, WantsToUseItem};
+use crate::level::LevelStore;
 use crate::loading::TextureAssets;
-use crate::map::{spawn_map, BlockTile, Map, Position, Viewshed};
+use crate::map::{spawn_map, BlockTile, Depth, Map, Position, Viewshed};
 use crate::player::Player;
-use crate::GameState;
+use crate::rng::GameRng;
+use crate::{GameState, PlayState};
+
+/// Chance for a monster to carry a healing potion
+const POTION_CHANCE: f64 = 0.2;
 
 pub struct MonsterPlugin;
 
@@ -17,24 +24,146 @@ pub struct Monster;
 #[derive(Resource)]
 pub struct MonsterTimer(Timer);
 
+/// The monster can't do anything for a few turns
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct Confused {
+    pub turns: i32,
+}
+
 /// This plugin handles monster related stuff like movement
 /// Monster logic is only active during the State `GameState::Playing`
 impl Plugin for MonsterPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(MonsterTimer(Timer::from_seconds(0.5, TimerMode::Repeating)))
+        app.register_type::<Confused>()
+            .insert_resource(MonsterTimer(Timer::from_seconds(0.5, TimerMode::Repeating)))
             .add_systems(OnEnter(GameState::Playing), spawn_monster.after(spawn_map))
             .add_systems(OnExit(GameState::Playing), clear_monster)
-            .add_systems(Update, monster_ai.run_if(in_state(GameState::Playing)));
+            .add_systems(
+                Update,
+                monster_ai
+                    .after(update_dijkstra_maps)
+                    .run_if(in_state(PlayState::Running))
+                    .run_if(in_state(GameState::Playing)),
+            );
     }
 }
 
-fn spawn_monster(mut commands: Commands, texture_assets: Res<TextureAssets>, map: Res<Map>) {
-    let mut rng = ThreadRng::default();
+pub(crate) fn spawn_monster(
+    mut commands: Commands,
+    texture_assets: Res<TextureAssets>,
+    map: Res<Map>,
+    depth: Res<Depth>,
+    levels: Res<LevelStore>,
+    mut rng: ResMut<GameRng>,
+) {
+    // Monsters that survived on a visited level come back where they were left
+    if let Some(level) = levels.get(&depth.0) {
+        level.monsters.iter().for_each(|monster| {
+            let idx = map.xy_to_index(monster.position.x, monster.position.y);
+            let entity = spawn_monster_at(
+                &mut commands,
+                &texture_assets,
+                &map,
+                idx,
+                monster.name.clone(),
+                monster.sprite,
+                monster.stats.clone(),
+            );
+            monster.items.iter().for_each(|item| {
+                spawn_item_in_backpack(&mut commands, &texture_assets, &map, entity, item);
+            });
+        });
+        return;
+    }
+
+    map.spawn_regions
+        .iter()
+        .enumerate()
+        .for_each(|(i, region)| {
+            let idx = region[rng.gen_range(0..region.len())];
+            let sprite =
+                map.get_tile_index_in_sprite_sheet(rng.gen_range(24..32), rng.gen_range(5..=9));
+
+            let entity = spawn_monster_at(
+                &mut commands,
+                &texture_assets,
+                &map,
+                idx,
+                format!("Monster #{}", i + 1),
+                sprite,
+                CombatStats {
+                    max_hp: 16,
+                    hp: 16,
+                    defense: 1,
+                    power: 3,
+                },
+            );
+            if rng.gen_bool(POTION_CHANCE) {
+                spawn_item_in_backpack(
+                    &mut commands,
+                    &texture_assets,
+                    &map,
+                    entity,
+                    "Health Potion",
+                );
+            }
+        });
+
+    // Monsters placed by prefabs, there can be any number of them in one place
+    map.spawn_list.iter().for_each(|(idx, name)| {
+        let Some((sprite, stats)) = monster_template(name) else {
+            return;
+        };
+        spawn_monster_at(
+            &mut commands,
+            &texture_assets,
+            &map,
+            *idx,
+            name.clone(),
+            map.get_tile_index_in_sprite_sheet(sprite.0, sprite.1),
+            stats,
+        );
+    });
+}
 
-    map.rooms.iter().enumerate().skip(1).for_each(|(i, room)| {
-        let monster_pos = room.center();
+/// Sprite position in the sheet and stats of the named monsters
+fn monster_template(name: &str) -> Option<((usize, usize), CombatStats)> {
+    match name {
+        "Goblin" => Some((
+            (29, 2),
+            CombatStats {
+                max_hp: 8,
+                hp: 8,
+                defense: 1,
+                power: 3,
+            },
+        )),
+        "Orc" => Some((
+            (26, 2),
+            CombatStats {
+                max_hp: 16,
+                hp: 16,
+                defense: 1,
+                power: 4,
+            },
+        )),
+        _ => None,
+    }
+}
 
-        commands.spawn((
+fn spawn_monster_at(
+    commands: &mut Commands,
+    texture_assets: &TextureAssets,
+    map: &Map,
+    idx: usize,
+    name: String,
+    sprite: usize,
+    stats: CombatStats,
+) -> Entity {
+    let monster_pos = (idx % map.cols, idx / map.cols);
+
+    commands
+        .spawn((
             SpriteSheetBundle {
                 transform: Transform {
                     translation: Vec3::new(
@@ -47,23 +176,15 @@ fn spawn_monster(mut commands: Commands, texture_assets: Res<TextureAssets>, map
                 },
                 texture: texture_assets.map_atlas.clone(),
                 atlas: TextureAtlas {
-                    index: map.get_tile_index_in_sprite_sheet(
-                        rng.gen_range(24..32),
-                        rng.gen_range(5..=9),
-                    ),
+                    index: sprite,
                     layout: texture_assets.map_atlas_layout.clone(),
                 },
                 ..default()
             },
-            Name::new(format!("Monster #{}", i)),
+            Name::new(name),
             Monster,
             BlockTile,
-            CombatStats {
-                max_hp: 16,
-                hp: 16,
-                defense: 1,
-                power: 3,
-            },
+            stats,
             Position {
                 x: monster_pos.0,
                 y: monster_pos.1,
@@ -73,11 +194,11 @@ fn spawn_monster(mut commands: Commands, texture_assets: Res<TextureAssets>, map
                 range: 8,
                 dirty: true,
             },
-        ));
-    });
+        ))
+        .id()
 }
 
-fn clear_monster(
+pub(crate) fn clear_monster(
     mut commands: Commands,
     q_monsters: Query<Entity, (With<Monster>, Without<Player>)>,
 ) {
@@ -86,6 +207,7 @@ fn clear_monster(
     })
 }
 
+#[allow(clippy::too_many_arguments)]
 fn monster_ai(
     mut commands: Commands,
     mut q_monsters: Query<
@@ -94,15 +216,19 @@ fn monster_ai(
             &mut Transform,
             &mut Position,
             &mut Viewshed,
-            &Name,
+            &CombatStats,
             &Visibility,
+            Option<&mut Confused>,
         ),
         (With<Monster>, Without<Player>),
     >,
     q_player: Query<(Entity, &Position), With<Player>>,
+    q_potions: Query<(Entity, &InBackpack), With<ProvidesHealing>>,
     time: Res<Time>,
     mut map: ResMut<Map>,
+    dijkstra_maps: Res<DijkstraMaps>,
     mut monster_timer: ResMut<MonsterTimer>,
+    mut ev_operate_door: EventWriter<OperateDoor>,
 ) {
     if !monster_timer.0.tick(time.delta()).finished() {
         return;
@@ -113,44 +239,79 @@ fn monster_ai(
     };
 
     q_monsters.iter_mut().for_each(
-        |(entity, mut transform, mut pos, mut viewshed, _name, visible)| {
+        |(entity, mut transform, mut pos, mut viewshed, stats, visible, confused)| {
+            if let Some(mut confused) = confused {
+                confused.turns -= 1;
+                if confused.turns <= 0 {
+                    commands.entity(entity).remove::<Confused>();
+                }
+                return;
+            }
+
             if viewshed.visible_tiles.contains(&player_pos.into()) {
                 let distance = DistanceAlg::Pythagoras.distance2d(
                     Point::new(pos.x, pos.y),
                     Point::new(player_pos.x, player_pos.y),
                 );
-                if distance < 1.5 {
-                    commands.entity(entity).with_children(|parent| {
-                        parent.spawn(WantsToMelee {
-                            target: player_entity.clone(),
+                // Hurt monsters drink a potion if they have one
+                if stats.hp * 2 <= stats.max_hp {
+                    if let Some((item, _)) = q_potions
+                        .iter()
+                        .find(|(_, backpack)| backpack.owner == entity)
+                    {
+                        commands.entity(entity).with_children(|parent| {
+                            parent.spawn(WantsToUseItem { item, target: None });
                         });
-                    });
-                    return;
+                        return;
+                    }
                 }
 
+                // Badly hurt monsters run away, and only fight back when cornered
+                let fleeing = stats.hp * 4 <= stats.max_hp;
+
                 let from = map.xy_to_index(pos.x, pos.y);
-                let to = map.xy_to_index(player_pos.x, player_pos.y);
-                let path = a_star_search(from, to, &*map);
+                let step = if fleeing {
+                    dijkstra_maps.step_away_from_player(&map, from)
+                } else if distance < 1.5 {
+                    None
+                } else {
+                    dijkstra_maps.step_towards_player(&map, from)
+                };
 
-                if path.success && path.steps.len() > 1 {
-                    let next = path.steps[1] as usize;
-                    pos.x = next % map.cols;
-                    pos.y = next / map.cols;
+                let Some(next) = step else {
+                    if distance < 1.5 {
+                        commands.entity(entity).with_children(|parent| {
+                            parent.spawn(WantsToMelee {
+                                target: player_entity,
+                            });
+                        });
+                    }
+                    return;
+                };
 
-                    transform.translation = Vec3::new(
-                        pos.x as f32 * map.tile_size as f32,
-                        pos.y as f32 * map.tile_size as f32,
-                        1.0,
-                    );
+                if map.get_tile(next % map.cols, next / map.cols).can_open() {
+                    ev_operate_door.send(OperateDoor {
+                        actor: entity,
+                        x: next % map.cols,
+                        y: next / map.cols,
+                    });
+                    return;
+                }
 
-                    viewshed.dirty = true;
+                pos.x = next % map.cols;
+                pos.y = next / map.cols;
 
-                    // Update blocked map flag while monster is moving to provent next monster from moving into it
-                    let idx = map.xy_to_index(pos.x, pos.y);
-                    map.blocked[idx] = true;
+                transform.translation = Vec3::new(
+                    pos.x as f32 * map.tile_size as f32,
+                    pos.y as f32 * map.tile_size as f32,
+                    1.0,
+                );
 
-                    // info!("{} can go to {:?}", name.as_str(), (pos.x, pos.y));
-                }
+                viewshed.dirty = true;
+
+                // Update blocked map flag while monster is moving to provent next monster from moving into it
+                map.blocked[from] = false;
+                map.blocked[next] = true;
             } else {
                 // if monsters can't see player, player can't see monsters. so update the viewshed
                 if *visible == Visibility::Visible {
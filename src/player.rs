@@ -2,8 +2,9 @@ use bevy::prelude::*;
 
 use crate::actions::Actions;
 use crate::combat::{CombatStats, WantsToMelee};
+use crate::door::{KeyRing, OperateDoor};
 use crate::loading::TextureAssets;
-use crate::map::{spawn_map, Map, Position, Viewshed};
+use crate::map::{spawn_map, Map, Position, Tile, Viewshed};
 use crate::GameState;
 
 pub struct PlayerPlugin;
@@ -25,11 +26,7 @@ impl Plugin for PlayerPlugin {
 }
 
 fn spawn_player(mut commands: Commands, texture_assets: Res<TextureAssets>, map: Res<Map>) {
-    let player_pos = if map.rooms.is_empty() {
-        (map.cols / 2, map.rows / 2)
-    } else {
-        map.rooms[0].center()
-    };
+    let player_pos = map.player_start;
 
     let player = commands
         .spawn((
@@ -67,6 +64,7 @@ fn spawn_player(mut commands: Commands, texture_assets: Res<TextureAssets>, map:
                 range: 8,
                 dirty: true,
             },
+            KeyRing::default(),
         ))
         .id();
 
@@ -84,6 +82,7 @@ pub fn player_input(
     map: Res<Map>,
     actions: Res<Actions>,
     q_combat_stats: Query<&mut CombatStats>,
+    mut ev_operate_door: EventWriter<OperateDoor>,
     mut q_player: Query<(Entity, &mut Transform, &mut Position, &mut Viewshed), With<Player>>,
 ) {
     if actions.player_movement.is_none() {
@@ -104,6 +103,16 @@ pub fn player_input(
             return;
         }
 
+        // Bumping into a door tries to open it instead of moving
+        if matches!(map.get_tile(x, y), Tile::DoorClosed | Tile::DoorLocked) {
+            ev_operate_door.send(OperateDoor {
+                actor: player_entity,
+                x,
+                y,
+            });
+            return;
+        }
+
         let idx = map.xy_to_index(x, y);
         if !map.blocked[idx] {
             pos.x = x;
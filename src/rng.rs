@@ -0,0 +1,62 @@
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::map::spawn_map;
+use crate::GameState;
+
+pub struct RngPlugin;
+
+/// This plugin owns the random number generator shared by the whole game
+/// Every run is seeded, either with `--seed <n>` / `ROGUELIKE_SEED=<n>` or with a random seed,
+/// so a run can be reproduced from the seed shown in the HUD
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SeedConfig(seed_from_env()))
+            .insert_resource(GameRng::new(0))
+            .add_systems(OnEnter(GameState::Playing), seed_run.before(spawn_map));
+    }
+}
+
+/// The seed asked for by the user, `None` to pick a new one every run
+#[derive(Resource)]
+pub struct SeedConfig(pub Option<u64>);
+
+#[derive(Resource, Deref, DerefMut)]
+pub struct GameRng {
+    seed: u64,
+    #[deref]
+    rng: StdRng,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+fn seed_from_env() -> Option<u64> {
+    let mut args = std::env::args();
+    while let Some(arg) = args.next() {
+        if arg == "--seed" {
+            return args.next().and_then(|seed| seed.parse().ok());
+        }
+    }
+
+    std::env::var("ROGUELIKE_SEED")
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+}
+
+fn seed_run(mut rng: ResMut<GameRng>, config: Res<SeedConfig>) {
+    let seed = config.0.unwrap_or_else(rand::random);
+    info!("Run seed: {}", seed);
+    *rng = GameRng::new(seed);
+}
@@ -0,0 +1,230 @@
+use bevy::prelude::*;
+use bracket_pathfinding::prelude::*;
+
+use crate::actions::{set_targeting_actions, Actions};
+use crate::item::{AreaOfEffect, InflictsDamage, WantsToThrowItem, WantsToUseItem};
+use crate::map::{Map, Position, Viewshed};
+use crate::monster::Monster;
+use crate::player::Player;
+use crate::{GameState, PlayState};
+
+pub struct TargetingPlugin;
+
+/// This plugin lets the player pick the tile a ranged item is used on
+/// The turns are paused meanwhile, a cursor is moved with the movement keys or the mouse over the tiles in range
+impl Plugin for TargetingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(PlayState::Targeting), aim_at_nearest_monster)
+            .add_systems(OnExit(PlayState::Targeting), clear_targeting)
+            .add_systems(OnExit(GameState::Playing), stop_targeting)
+            .add_systems(
+                Update,
+                (
+                    move_cursor.after(set_targeting_actions),
+                    confirm_target,
+                    draw_preview,
+                )
+                    .chain()
+                    .run_if(in_state(PlayState::Targeting))
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+/// The item waiting for a target and where the cursor is
+#[derive(Resource, Debug)]
+pub struct Targeting {
+    pub item: Entity,
+    pub range: i32,
+    /// Tiles around the target that are hit as well
+    pub radius: i32,
+    pub cursor: Position,
+    /// The item is thrown at the target instead of being used
+    pub throw: bool,
+}
+
+/// Highlights the tiles in range and the ones that would be hit
+#[derive(Component)]
+pub struct TargetingPreview;
+
+/// Only tiles the player can see and that are close enough can be targeted
+fn in_range(targeting: &Targeting, player_pos: &Position, viewshed: &Viewshed, pos: Point) -> bool {
+    viewshed.visible_tiles.contains(&pos)
+        && DistanceAlg::Pythagoras.distance2d(player_pos.into(), pos) <= targeting.range as f32
+}
+
+/// The tile under the mouse
+fn mouse_tile(actions: &Actions, map: &Map) -> Option<Point> {
+    let world = actions.cursor_world?;
+    let tile_size = map.tile_size as f32;
+    Some(Point::new(
+        (world.x / tile_size).round() as i32,
+        (world.y / tile_size).round() as i32,
+    ))
+}
+
+/// Starts the cursor on the closest monster in range, it stays on the player when there is none
+fn aim_at_nearest_monster(
+    mut targeting: ResMut<Targeting>,
+    q_player: Query<(&Position, &Viewshed), With<Player>>,
+    q_monsters: Query<&Position, With<Monster>>,
+) {
+    let Ok((player_pos, viewshed)) = q_player.get_single() else {
+        return;
+    };
+
+    let distance =
+        |pos: &Position| DistanceAlg::Pythagoras.distance2d(player_pos.into(), pos.into());
+    if let Some(nearest) = q_monsters
+        .iter()
+        .filter(|pos| in_range(&targeting, player_pos, viewshed, (*pos).into()))
+        .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+    {
+        targeting.cursor = *nearest;
+    }
+}
+
+fn move_cursor(
+    actions: Res<Actions>,
+    map: Res<Map>,
+    mut targeting: ResMut<Targeting>,
+    q_player: Query<(&Position, &Viewshed), With<Player>>,
+) {
+    let Ok((player_pos, viewshed)) = q_player.get_single() else {
+        return;
+    };
+
+    let target = if let Some(tile) = mouse_tile(&actions, &map) {
+        tile
+    } else if let Some((dx, dy)) = actions.cursor_movement {
+        Point::new(
+            targeting.cursor.x as i32 + dx,
+            targeting.cursor.y as i32 + dy,
+        )
+    } else {
+        return;
+    };
+
+    if map.in_bounds(target) && in_range(&targeting, player_pos, viewshed, target) {
+        targeting.cursor = target.into();
+    }
+}
+
+fn confirm_target(
+    mut commands: Commands,
+    actions: Res<Actions>,
+    map: Res<Map>,
+    targeting: Res<Targeting>,
+    mut next_state: ResMut<NextState<PlayState>>,
+    q_player: Query<(Entity, &Position, &Viewshed), With<Player>>,
+    q_damaging: Query<(), Or<(With<InflictsDamage>, With<AreaOfEffect>)>>,
+) {
+    if actions.cancel {
+        info!("Never mind");
+        next_state.set(PlayState::Running);
+        return;
+    }
+    if !actions.confirm {
+        return;
+    }
+    let Ok((player_entity, player_pos, viewshed)) = q_player.get_single() else {
+        return;
+    };
+
+    let cursor = Point::from(targeting.cursor);
+    // Clicks outside the range never moved the cursor there, they must not pick it either
+    let picked = if actions.click {
+        mouse_tile(&actions, &map)
+    } else {
+        Some(cursor)
+    };
+    if picked != Some(cursor) || !in_range(&targeting, player_pos, viewshed, cursor) {
+        info!("That is out of range");
+        return;
+    }
+    let hurts = targeting.throw || q_damaging.contains(targeting.item);
+    if hurts && cursor == Point::from(player_pos) {
+        info!("You would only hurt yourself");
+        return;
+    }
+
+    commands.entity(player_entity).with_children(|parent| {
+        if targeting.throw {
+            parent.spawn(WantsToThrowItem {
+                item: targeting.item,
+                target: targeting.cursor,
+            });
+        } else {
+            parent.spawn(WantsToUseItem {
+                item: targeting.item,
+                target: Some(targeting.cursor),
+            });
+        }
+    });
+    next_state.set(PlayState::Running);
+}
+
+fn draw_preview(
+    mut commands: Commands,
+    map: Res<Map>,
+    targeting: Res<Targeting>,
+    q_player: Query<(&Position, &Viewshed), With<Player>>,
+    q_preview: Query<Entity, With<TargetingPreview>>,
+) {
+    if !targeting.is_changed() {
+        return;
+    }
+    let Ok((player_pos, viewshed)) = q_player.get_single() else {
+        return;
+    };
+
+    q_preview.iter().for_each(|preview| {
+        commands.entity(preview).despawn_recursive();
+    });
+
+    let mut highlight = |pos: Point, color: Color, z: f32| {
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::splat(map.tile_size as f32)),
+                    ..default()
+                },
+                transform: Transform::from_xyz(
+                    pos.x as f32 * map.tile_size as f32,
+                    pos.y as f32 * map.tile_size as f32,
+                    z,
+                ),
+                ..default()
+            },
+            Name::new("Targeting preview"),
+            TargetingPreview,
+        ));
+    };
+
+    viewshed
+        .visible_tiles
+        .iter()
+        .filter(|pos| in_range(&targeting, player_pos, viewshed, **pos))
+        .for_each(|pos| highlight(*pos, Color::rgba(0.2, 0.4, 1.0, 0.25), 2.0));
+
+    let cursor = Point::from(targeting.cursor);
+    if targeting.radius > 0 {
+        field_of_view(cursor, targeting.radius, &*map)
+            .into_iter()
+            .filter(|pos| map.in_bounds(*pos))
+            .for_each(|pos| highlight(pos, Color::rgba(1.0, 0.5, 0.0, 0.35), 2.1));
+    }
+    highlight(cursor, Color::rgba(1.0, 1.0, 0.0, 0.5), 2.2);
+}
+
+fn clear_targeting(mut commands: Commands, q_preview: Query<Entity, With<TargetingPreview>>) {
+    commands.remove_resource::<Targeting>();
+    q_preview.iter().for_each(|preview| {
+        commands.entity(preview).despawn_recursive();
+    });
+}
+
+fn stop_targeting(mut next_state: ResMut<NextState<PlayState>>) {
+    next_state.set(PlayState::Running);
+}
@@ -0,0 +1,347 @@
+use bevy::prelude::*;
+use bevy::reflect::TypePath;
+use bevy::render::mesh::{
+    Indices, MeshVertexAttribute, MeshVertexBufferLayout, PrimitiveTopology, VertexAttributeValues,
+};
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{
+    AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError, VertexFormat,
+};
+use bevy::sprite::{
+    Material2d, Material2dKey, Material2dPlugin, MaterialMesh2dBundle, Mesh2dHandle,
+};
+use bracket_pathfinding::prelude::{DistanceAlg, Point};
+
+use crate::loading::TextureAssets;
+use crate::map::Map;
+use crate::GameState;
+
+/// Width and height in tiles of every mesh the map is cut into
+pub const CHUNK_SIZE: usize = 32;
+/// How much of the tile corner is in view, and whether the tile has been seen at all
+pub const ATTRIBUTE_TILE_FOG: MeshVertexAttribute =
+    MeshVertexAttribute::new("TileFog", 970_431_208, VertexFormat::Float32x2);
+
+pub struct TilemapPlugin;
+
+/// This plugin draws the map as a few big meshes instead of a sprite per tile
+/// Tiles out of view are tinted by the fog of war, see `FogOfWar`
+impl Plugin for TilemapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(Material2dPlugin::<TilemapMaterial>::default())
+            .init_resource::<MapChunks>()
+            .init_resource::<FogOfWar>()
+            .register_type::<FogOfWar>()
+            .add_systems(
+                Update,
+                apply_fog_of_war.run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+/// How the tiles out of view are drawn
+#[derive(Resource, Reflect, Debug, Clone)]
+#[reflect(Resource)]
+pub struct FogOfWar {
+    /// Multiplied with the remembered tiles
+    pub remembered_color: Color,
+    /// Number of tiles over which the view fades out towards the edge of the viewshed
+    pub falloff: f32,
+}
+
+impl Default for FogOfWar {
+    fn default() -> Self {
+        FogOfWar {
+            remembered_color: Color::rgb(0.35, 0.35, 0.45),
+            falloff: 3.0,
+        }
+    }
+}
+
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct TilemapMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    pub atlas: Handle<Image>,
+    /// `FogOfWar::remembered_color` in linear space
+    #[uniform(2)]
+    pub remembered_color: Vec4,
+}
+
+impl Material2d for TilemapMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "shaders/tilemap.wgsl".into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        "shaders/tilemap.wgsl".into()
+    }
+
+    fn specialize(
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: Material2dKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let vertex_layout = layout.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_UV_0.at_shader_location(1),
+            ATTRIBUTE_TILE_FOG.at_shader_location(2),
+        ])?;
+        descriptor.vertex.buffers = vec![vertex_layout];
+        Ok(())
+    }
+}
+
+#[derive(Component)]
+pub struct TileChunk;
+
+/// The meshes of the map, by chunk index
+#[derive(Resource, Default)]
+pub struct MapChunks {
+    chunk_cols: usize,
+    meshes: Vec<Handle<Mesh>>,
+    material: Handle<TilemapMaterial>,
+}
+
+impl MapChunks {
+    /// Writes the sprite and fog of the given tiles into their chunk meshes
+    pub fn redraw_tiles(
+        &self,
+        map: &Map,
+        meshes: &mut Assets<Mesh>,
+        fog: &TileFog,
+        mut tiles: Vec<usize>,
+    ) {
+        if self.meshes.is_empty() {
+            return;
+        }
+
+        // The fog of a tile blends into its neighbours, so their corners have to follow
+        let neighbours: Vec<usize> = tiles
+            .iter()
+            .flat_map(|idx| {
+                let (x, y) = (idx % map.cols, idx / map.cols);
+                (y.saturating_sub(1)..=(y + 1).min(map.rows - 1)).flat_map(move |ny| {
+                    (x.saturating_sub(1)..=(x + 1).min(map.cols - 1))
+                        .map(move |nx| ny * map.cols + nx)
+                })
+            })
+            .collect();
+        tiles.extend(neighbours);
+        // Every chunk mesh is only touched once
+        tiles.sort_unstable_by_key(|idx| (self.chunk_index(map, *idx), *idx));
+        tiles.dedup();
+
+        for chunk_tiles in
+            tiles.chunk_by(|a, b| self.chunk_index(map, *a) == self.chunk_index(map, *b))
+        {
+            let chunk = self.chunk_index(map, chunk_tiles[0]);
+            let Some(mesh) = self.meshes.get(chunk).and_then(|mesh| meshes.get_mut(mesh)) else {
+                continue;
+            };
+            let chunk_width = chunk_width(map, chunk % self.chunk_cols);
+
+            for idx in chunk_tiles {
+                let (x, y) = (idx % map.cols, idx / map.cols);
+                let vertex = ((y % CHUNK_SIZE) * chunk_width + x % CHUNK_SIZE) * 4;
+
+                if let Some(VertexAttributeValues::Float32x2(uvs)) =
+                    mesh.attribute_mut(Mesh::ATTRIBUTE_UV_0)
+                {
+                    uvs[vertex..vertex + 4].copy_from_slice(&tile_uvs(map, x, y));
+                }
+                if let Some(VertexAttributeValues::Float32x2(fogs)) =
+                    mesh.attribute_mut(ATTRIBUTE_TILE_FOG)
+                {
+                    fogs[vertex..vertex + 4].copy_from_slice(&fog.tile_fog(map, x, y));
+                }
+            }
+        }
+    }
+
+    fn chunk_index(&self, map: &Map, idx: usize) -> usize {
+        let (x, y) = (idx % map.cols, idx / map.cols);
+        (y / CHUNK_SIZE) * self.chunk_cols + x / CHUNK_SIZE
+    }
+}
+
+/// How much every tile is in view, fading out towards the edge of the viewshed
+#[derive(Default)]
+pub struct TileFog {
+    /// Where the view comes from and how far it goes, everything in view is fully lit without it
+    viewer: Option<(Point, f32)>,
+    falloff: f32,
+}
+
+impl TileFog {
+    pub fn new(fog_of_war: &FogOfWar, viewer: Option<(Point, i32)>) -> Self {
+        TileFog {
+            viewer: viewer.map(|(center, range)| (center, range as f32)),
+            falloff: fog_of_war.falloff.max(f32::EPSILON),
+        }
+    }
+
+    fn visibility(&self, map: &Map, x: usize, y: usize) -> f32 {
+        if !map.visible_tiles[map.xy_to_index(x, y)] {
+            return 0.0;
+        }
+        let Some((center, range)) = self.viewer else {
+            return 1.0;
+        };
+
+        let distance = DistanceAlg::Pythagoras.distance2d(center, Point::new(x, y));
+        ((range + 0.5 - distance) / self.falloff).clamp(0.0, 1.0)
+    }
+
+    /// Average visibility of the tiles sharing the corner below and left of the tile (x, y)
+    fn corner(&self, map: &Map, x: usize, y: usize) -> f32 {
+        let (mut sum, mut count) = (0.0, 0.0);
+        for ty in y.saturating_sub(1)..=y.min(map.rows - 1) {
+            for tx in x.saturating_sub(1)..=x.min(map.cols - 1) {
+                sum += self.visibility(map, tx, ty);
+                count += 1.0;
+            }
+        }
+        sum / count
+    }
+
+    /// Fog of the four corners of the tile, in the same order as the quad corners
+    fn tile_fog(&self, map: &Map, x: usize, y: usize) -> [[f32; 2]; 4] {
+        let revealed = if map.revealed_tiles[map.xy_to_index(x, y)] {
+            1.0
+        } else {
+            0.0
+        };
+        [
+            [self.corner(map, x, y), revealed],
+            [self.corner(map, x + 1, y), revealed],
+            [self.corner(map, x + 1, y + 1), revealed],
+            [self.corner(map, x, y + 1), revealed],
+        ]
+    }
+}
+
+/// Spawns the meshes covering the whole map
+pub fn spawn_tile_chunks(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<TilemapMaterial>,
+    map: &Map,
+    texture_assets: &TextureAssets,
+    fog_of_war: &FogOfWar,
+) -> MapChunks {
+    let material = materials.add(TilemapMaterial {
+        atlas: texture_assets.map_atlas.clone(),
+        remembered_color: fog_of_war.remembered_color.as_linear_rgba_f32().into(),
+    });
+    let fog = TileFog::new(fog_of_war, None);
+    let chunk_cols = map.cols.div_ceil(CHUNK_SIZE);
+    let chunk_rows = map.rows.div_ceil(CHUNK_SIZE);
+
+    let mut chunk_meshes = Vec::with_capacity(chunk_cols * chunk_rows);
+    for chunk_y in 0..chunk_rows {
+        for chunk_x in 0..chunk_cols {
+            let mesh = meshes.add(chunk_mesh(map, &fog, chunk_x, chunk_y));
+            commands.spawn((
+                MaterialMesh2dBundle {
+                    mesh: Mesh2dHandle(mesh.clone()),
+                    material: material.clone(),
+                    transform: Transform::from_xyz(
+                        (chunk_x * CHUNK_SIZE * map.tile_size) as f32,
+                        (chunk_y * CHUNK_SIZE * map.tile_size) as f32,
+                        0.0,
+                    ),
+                    ..default()
+                },
+                Name::new(format!("Tile chunk {}x{}", chunk_x, chunk_y)),
+                TileChunk,
+            ));
+            chunk_meshes.push(mesh);
+        }
+    }
+
+    MapChunks {
+        chunk_cols,
+        meshes: chunk_meshes,
+        material,
+    }
+}
+
+/// A quad for every tile of the chunk, row by row from the bottom left
+fn chunk_mesh(map: &Map, fog: &TileFog, chunk_x: usize, chunk_y: usize) -> Mesh {
+    let (x1, y1) = (chunk_x * CHUNK_SIZE, chunk_y * CHUNK_SIZE);
+    let (x2, y2) = (
+        (x1 + CHUNK_SIZE).min(map.cols),
+        (y1 + CHUNK_SIZE).min(map.rows),
+    );
+    let tiles = (x2 - x1) * (y2 - y1);
+    let half = map.tile_size as f32 / 2.0;
+
+    let mut positions = Vec::with_capacity(tiles * 4);
+    let mut uvs = Vec::with_capacity(tiles * 4);
+    let mut fogs = Vec::with_capacity(tiles * 4);
+    let mut indices = Vec::with_capacity(tiles * 6);
+    for y in y1..y2 {
+        for x in x1..x2 {
+            // Tiles are centered on their position, like the sprites on top of them
+            let cx = ((x - x1) * map.tile_size) as f32;
+            let cy = ((y - y1) * map.tile_size) as f32;
+            let first = positions.len() as u32;
+            positions.extend([
+                [cx - half, cy - half, 0.0],
+                [cx + half, cy - half, 0.0],
+                [cx + half, cy + half, 0.0],
+                [cx - half, cy + half, 0.0],
+            ]);
+            uvs.extend(tile_uvs(map, x, y));
+            fogs.extend(fog.tile_fog(map, x, y));
+            indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+    }
+
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+    .with_inserted_attribute(ATTRIBUTE_TILE_FOG, fogs)
+    .with_inserted_indices(Indices::U32(indices))
+}
+
+fn chunk_width(map: &Map, chunk_x: usize) -> usize {
+    (map.cols - chunk_x * CHUNK_SIZE).min(CHUNK_SIZE)
+}
+
+/// Corners of the tile sprite in the atlas, in the same order as the quad corners
+fn tile_uvs(map: &Map, x: usize, y: usize) -> [[f32; 2]; 4] {
+    let (grid_cols, grid_rows) = (map.tileset_grids.0 as f32, map.tileset_grids.1 as f32);
+    let sprite = map.tile_sprite(x, y);
+    let (col, row) = (
+        (sprite % map.tileset_grids.0) as f32,
+        (sprite / map.tileset_grids.0) as f32,
+    );
+    // Stay off the sprite edges so the neighbours in the atlas don't bleed in
+    let inset = 0.01;
+    let (u1, u2) = ((col + inset) / grid_cols, (col + 1.0 - inset) / grid_cols);
+    let (v1, v2) = ((row + inset) / grid_rows, (row + 1.0 - inset) / grid_rows);
+    [[u1, v2], [u2, v2], [u2, v1], [u1, v1]]
+}
+
+/// Keeps the material and meshes in line with the fog of war settings
+fn apply_fog_of_war(
+    fog_of_war: Res<FogOfWar>,
+    map_chunks: Res<MapChunks>,
+    mut map: ResMut<Map>,
+    mut materials: ResMut<Assets<TilemapMaterial>>,
+) {
+    if !fog_of_war.is_changed() || fog_of_war.is_added() {
+        return;
+    }
+
+    if let Some(material) = materials.get_mut(&map_chunks.material) {
+        material.remembered_color = fog_of_war.remembered_color.as_linear_rgba_f32().into();
+    }
+    // The falloff changes how every tile in view looks
+    map.bypass_change_detection().mark_all_dirty();
+}